@@ -0,0 +1,524 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_nats::{Client, Subscriber};
+use futures::{StreamExt, future};
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Example, Filesize, IntoValue, LabeledError, PipelineData, Record, Signature, Span, Spanned,
+    SyntaxShape, Type, Value,
+};
+use tokio::{
+    select,
+    task::{JoinError, JoinHandle},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Nuts,
+    commands::{
+        Publish, Request, SignatureExt, connect, count_from_flag, get_count_flag, per_second,
+        publish::PublishSettings, rtt::percentile,
+    },
+    connections::ClientHandle,
+    message::OutgoingMessage,
+    rate_limiter::{Rate, RateLimiter},
+};
+
+/// Queue group of the responders of `--request`, so every request is answered once
+const RESPONDER_QUEUE_GROUP: &str = "nuts-bench";
+/// Time the subscribers get to receive the messages in flight after the publishers finished.
+/// Core NATS may drop messages, so subscribers do not wait for every message forever.
+const SUBSCRIBER_GRACE_PERIOD: Duration = Duration::from_secs(2);
+/// Payloads at least this large carry their publish time for measuring latency
+const TIMESTAMP_SIZE: usize = 16;
+
+#[derive(Debug)]
+pub(crate) struct Bench;
+
+#[derive(Debug, Clone)]
+struct BenchSettings {
+    subject: Spanned<String>,
+    publishers: usize,
+    subscribers: usize,
+    msgs: usize,
+    size: usize,
+    request: bool,
+    rate: Option<Arc<RateLimiter>>,
+}
+
+/// Statistics of a single publisher, subscriber or responder
+#[derive(Debug)]
+struct ClientStats {
+    kind: &'static str,
+    id: usize,
+    msgs: usize,
+    bytes: usize,
+    elapsed: Duration,
+    latencies: Vec<Duration>,
+}
+
+impl PluginCommand for Bench {
+    type Plugin = Nuts;
+
+    fn name(&self) -> &str {
+        "nuts bench"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required("subject", SyntaxShape::String, "Subject to benchmark")
+            .named(
+                "pub",
+                SyntaxShape::Int,
+                "Number of concurrent publishers. Defaults to 1",
+                None,
+            )
+            .named(
+                "sub",
+                SyntaxShape::Int,
+                "Number of concurrent subscribers, or responders with `--request`. Defaults to 0, or 1 with `--request`",
+                None,
+            )
+            .named(
+                "msgs",
+                SyntaxShape::Int,
+                "Number of messages to publish, shared between the publishers. Defaults to 100000",
+                Some('m'),
+            )
+            .named(
+                "size",
+                SyntaxShape::Filesize,
+                "Size of the message payloads. Defaults to 128b",
+                Some('s'),
+            )
+            .named(
+                "rate",
+                SyntaxShape::OneOf(vec![SyntaxShape::Int, SyntaxShape::String]),
+                "Maximum number of messages per second, or per duration like 100/10ms",
+                Some('r'),
+            )
+            .switch(
+                "request",
+                "Send requests answered by the subscribers and measure the round trip latency",
+                None,
+            )
+            .connection_flags()
+            .input_output_type(Type::Nothing, Type::record())
+    }
+
+    fn description(&self) -> &str {
+        "Measure the publish, subscribe or request throughput and latency"
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        vec!["nats", "bench", "benchmark", "throughput", "latency"]
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                example: "nuts bench bench.test --pub 2 --sub 2 --msgs 1000000",
+                description: "Measure the throughput of two publishers and two subscribers",
+                result: None,
+            },
+            Example {
+                example: "nuts bench bench.test --request --sub 4 --msgs 10000 | get latency",
+                description: "Measure the request latency with four responders",
+                result: None,
+            },
+            Example {
+                example: "nuts bench bench.test --sub 1 --rate 1000 --size 1kb",
+                description: "Measure the latency of a steady stream of messages",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let request = call.has_flag("request")?;
+        let settings = BenchSettings {
+            subject: call.req(0)?,
+            publishers: get_count_flag(call, "pub")?.unwrap_or(1),
+            subscribers: match call.get_flag::<Spanned<i64>>("sub")? {
+                // Without subscribers only the publish throughput is measured
+                Some(Spanned { item: 0, .. }) if !request => 0,
+                Some(count) => count_from_flag("sub", count)?,
+                None => usize::from(request),
+            },
+            msgs: get_count_flag(call, "msgs")?.unwrap_or(100_000),
+            size: match call.get_flag::<Spanned<Filesize>>("size")? {
+                Some(Spanned { item, span }) => usize::try_from(item.get()).map_err(|_| {
+                    LabeledError::new("Invalid size").with_label("must not be negative", span)
+                })?,
+                None => 128,
+            },
+            request,
+            rate: call
+                .get_flag_value("rate")
+                .map(Rate::from_value)
+                .transpose()?
+                .map(|rate| Arc::new(RateLimiter::new(rate, 1))),
+        };
+
+        // Checks the connection flags and connects lazily, so there are settings to connect with
+        let client = plugin.client(engine, call)?;
+        // Every publisher and subscriber has its own connection like separate clients, which also
+        // lets the subscribers receive the messages of connections opened with `--no-echo`
+        let clients = (0..settings.publishers + settings.subscribers)
+            .map(|_| {
+                Ok(ClientHandle::temporary(
+                    connect::connect_like(plugin, engine, call)?,
+                    plugin.lifecycle.activity(engine)?,
+                ))
+            })
+            .collect::<Result<Vec<ClientHandle>, LabeledError>>()?;
+        let cancellation = CancellationToken::new();
+        let _signal_guard = engine.register_signal_handler(Box::new({
+            let cancellation = cancellation.clone();
+            move |_| cancellation.cancel()
+        }))?;
+        let result = plugin.runtime.block_on(async {
+            let result = Self::bench(&clients, &settings, &cancellation).await;
+            future::try_join_all(clients.into_iter().map(ClientHandle::close)).await?;
+            client.close().await?;
+            result
+        })?;
+        Ok(PipelineData::Value(
+            Self::summary(&settings, result, call.head),
+            None,
+        ))
+    }
+}
+
+impl Bench {
+    /// Returns the elapsed time of the publishers and the statistics of every client.
+    /// The publishers use the first clients and the subscribers the rest.
+    async fn bench(
+        clients: &[ClientHandle],
+        settings: &BenchSettings,
+        cancellation: &CancellationToken,
+    ) -> Result<(Duration, Vec<ClientStats>), LabeledError> {
+        let subject = &settings.subject;
+        let subscribers_done = cancellation.child_token();
+        // Stops the subscribers if publishing fails
+        let _subscribers_guard = subscribers_done.clone().drop_guard();
+        let (publisher_clients, subscriber_clients) = clients.split_at(settings.publishers);
+        let mut subscriber_tasks = Vec::with_capacity(settings.subscribers);
+        for (id, client) in subscriber_clients.iter().enumerate() {
+            let task: JoinHandle<Result<ClientStats, LabeledError>> = if settings.request {
+                let subscription = client
+                    .queue_subscribe(subject.item.clone(), RESPONDER_QUEUE_GROUP.to_owned())
+                    .await
+                    .map_err(|error| nats_error("Failed to subscribe", error, subject.span))?;
+                tokio::spawn(Self::respond(
+                    id,
+                    Client::clone(client),
+                    subscription,
+                    subject.span,
+                    subscribers_done.clone(),
+                ))
+            } else {
+                let subscription = client
+                    .subscribe(subject.item.clone())
+                    .await
+                    .map_err(|error| nats_error("Failed to subscribe", error, subject.span))?;
+                tokio::spawn(Self::subscribe(
+                    id,
+                    subscription,
+                    settings.msgs,
+                    subscribers_done.clone(),
+                ))
+            };
+            subscriber_tasks.push(task);
+            // Make sure the subscription is registered on the server before publishing
+            client
+                .flush()
+                .await
+                .map_err(|error| nats_error("Failed to flush connection", error, subject.span))?;
+        }
+
+        let start = Instant::now();
+        let publisher_tasks = publisher_clients.iter().enumerate().map(|(id, client)| {
+            // Spread the remainder over the first publishers
+            let msgs = settings.msgs / settings.publishers
+                + usize::from(id < settings.msgs % settings.publishers);
+            tokio::spawn(Self::publish(
+                id,
+                Client::clone(client),
+                settings.clone(),
+                msgs,
+                cancellation.clone(),
+            ))
+        });
+        let mut stats = future::try_join_all(publisher_tasks)
+            .await
+            .map_err(task_error)?
+            .into_iter()
+            .collect::<Result<Vec<ClientStats>, LabeledError>>()?;
+        let elapsed = start.elapsed();
+
+        if settings.request {
+            // Every request was answered once the publishers finished
+            subscribers_done.cancel();
+        } else {
+            // Subscribers that received every message are done before the grace period ends
+            tokio::spawn(async move {
+                tokio::time::sleep(SUBSCRIBER_GRACE_PERIOD).await;
+                subscribers_done.cancel();
+            });
+        }
+        for stat in future::try_join_all(subscriber_tasks)
+            .await
+            .map_err(task_error)?
+        {
+            stats.push(stat?);
+        }
+        Ok((elapsed, stats))
+    }
+
+    async fn publish(
+        id: usize,
+        client: Client,
+        settings: BenchSettings,
+        msgs: usize,
+        cancellation: CancellationToken,
+    ) -> Result<ClientStats, LabeledError> {
+        let mut stats = ClientStats::new("pub", id);
+        let publish_settings = PublishSettings {
+            subject: Some(settings.subject.item.clone()),
+            ..Default::default()
+        };
+        let start = Instant::now();
+        for _ in 0..msgs {
+            if let Some(rate) = &settings.rate {
+                select! {
+                    _ = cancellation.cancelled() => break,
+                    _ = rate.acquire() => {}
+                }
+            }
+            if cancellation.is_cancelled() {
+                break;
+            }
+            let payload = timestamped_payload(settings.size);
+            if settings.request {
+                let message = OutgoingMessage {
+                    subject: None,
+                    reply: None,
+                    headers: None,
+                    payload,
+                };
+                let sent = Instant::now();
+                Request::request(&client, &settings.subject, None, message).await?;
+                stats.latencies.push(sent.elapsed());
+            } else {
+                let payload = Value::binary(payload, settings.subject.span);
+                Publish::publish_value(&client, &publish_settings, payload).await?;
+            }
+            stats.msgs += 1;
+            stats.bytes += settings.size;
+        }
+        client.flush().await.map_err(|error| {
+            nats_error("Failed to flush connection", error, settings.subject.span)
+        })?;
+        stats.elapsed = start.elapsed();
+        Ok(stats)
+    }
+
+    async fn subscribe(
+        id: usize,
+        mut subscription: Subscriber,
+        expected_msgs: usize,
+        done: CancellationToken,
+    ) -> Result<ClientStats, LabeledError> {
+        let mut stats = ClientStats::new("sub", id);
+        let mut first_received = None;
+        while stats.msgs < expected_msgs {
+            let message = select! {
+                _ = done.cancelled() => break,
+                message = subscription.next() => match message {
+                    Some(message) => message,
+                    None => break,
+                }
+            };
+            let first_received = *first_received.get_or_insert_with(Instant::now);
+            stats.msgs += 1;
+            stats.bytes += message.payload.len();
+            stats.elapsed = first_received.elapsed();
+            if let Some(latency) = latency(&message.payload) {
+                stats.latencies.push(latency);
+            }
+        }
+        Ok(stats)
+    }
+
+    async fn respond(
+        id: usize,
+        client: Client,
+        mut subscription: Subscriber,
+        span: Span,
+        done: CancellationToken,
+    ) -> Result<ClientStats, LabeledError> {
+        let mut stats = ClientStats::new("responder", id);
+        let mut first_received = None;
+        loop {
+            let message = select! {
+                _ = done.cancelled() => break,
+                message = subscription.next() => match message {
+                    Some(message) => message,
+                    None => break,
+                }
+            };
+            let Some(reply) = message.reply else {
+                continue;
+            };
+            let first_received = *first_received.get_or_insert_with(Instant::now);
+            stats.msgs += 1;
+            stats.bytes += message.payload.len();
+            client
+                .publish(reply, message.payload)
+                .await
+                .map_err(|error| nats_error("Failed to reply", error, span))?;
+            stats.elapsed = first_received.elapsed();
+        }
+        Ok(stats)
+    }
+
+    fn summary(
+        settings: &BenchSettings,
+        (elapsed, stats): (Duration, Vec<ClientStats>),
+        span: Span,
+    ) -> Value {
+        let duration = |duration: Duration| Value::duration(duration.as_nanos() as i64, span);
+        let published = stats
+            .iter()
+            .filter(|stats| stats.kind == "pub")
+            .map(|stats| stats.msgs)
+            .sum::<usize>();
+        let mut latencies = stats
+            .iter()
+            .flat_map(|stats| stats.latencies.iter().copied())
+            .collect::<Vec<Duration>>();
+        latencies.sort();
+        let latency = if latencies.is_empty() {
+            Value::nothing(span)
+        } else {
+            let average = latencies.iter().sum::<Duration>() / latencies.len() as u32;
+            Record::from_iter([
+                ("min".to_owned(), duration(latencies[0])),
+                ("avg".to_owned(), duration(average)),
+                ("p50".to_owned(), duration(percentile(&latencies, 50.0))),
+                ("p90".to_owned(), duration(percentile(&latencies, 90.0))),
+                ("p99".to_owned(), duration(percentile(&latencies, 99.0))),
+                ("max".to_owned(), duration(latencies[latencies.len() - 1])),
+            ])
+            .into_value(span)
+        };
+        let clients = stats
+            .into_iter()
+            .map(|stats| {
+                Record::from_iter([
+                    ("kind".to_owned(), stats.kind.into_value(span)),
+                    ("id".to_owned(), Value::int(stats.id as i64, span)),
+                    ("msgs".to_owned(), Value::int(stats.msgs as i64, span)),
+                    (
+                        "bytes".to_owned(),
+                        Value::filesize(stats.bytes as i64, span),
+                    ),
+                    ("elapsed".to_owned(), duration(stats.elapsed)),
+                    (
+                        "msgs_per_sec".to_owned(),
+                        Value::float(per_second(stats.msgs, stats.elapsed), span),
+                    ),
+                ])
+                .into_value(span)
+            })
+            .collect::<Vec<Value>>();
+        Record::from_iter([
+            (
+                "subject".to_owned(),
+                settings.subject.item.clone().into_value(span),
+            ),
+            (
+                "mode".to_owned(),
+                if settings.request {
+                    "request"
+                } else {
+                    "pub/sub"
+                }
+                .into_value(span),
+            ),
+            ("msgs".to_owned(), Value::int(published as i64, span)),
+            (
+                "size".to_owned(),
+                Value::filesize(settings.size as i64, span),
+            ),
+            ("elapsed".to_owned(), duration(elapsed)),
+            (
+                "msgs_per_sec".to_owned(),
+                Value::float(per_second(published, elapsed), span),
+            ),
+            (
+                "bytes_per_sec".to_owned(),
+                Value::filesize(per_second(published * settings.size, elapsed) as i64, span),
+            ),
+            ("latency".to_owned(), latency),
+            ("clients".to_owned(), clients.into_value(span)),
+        ])
+        .into_value(span)
+    }
+}
+
+impl ClientStats {
+    fn new(kind: &'static str, id: usize) -> Self {
+        Self {
+            kind,
+            id,
+            msgs: 0,
+            bytes: 0,
+            elapsed: Duration::ZERO,
+            latencies: Vec::new(),
+        }
+    }
+}
+
+/// Builds a payload starting with the current time, if it is large enough to hold it
+fn timestamped_payload(size: usize) -> Vec<u8> {
+    let mut payload = vec![0; size];
+    if size >= TIMESTAMP_SIZE {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        payload[..TIMESTAMP_SIZE].copy_from_slice(&now.to_le_bytes());
+    }
+    payload
+}
+
+/// Returns the time since the payload was built by [`timestamped_payload`]
+fn latency(payload: &[u8]) -> Option<Duration> {
+    let sent = u128::from_le_bytes(payload.get(..TIMESTAMP_SIZE)?.try_into().ok()?);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .ok()?
+        .as_nanos();
+    u64::try_from(now.checked_sub(sent)?)
+        .ok()
+        .map(Duration::from_nanos)
+}
+
+fn nats_error(msg: &str, error: impl std::error::Error, span: Span) -> LabeledError {
+    LabeledError::new(msg).with_label(error.to_string(), span)
+}
+
+fn task_error(error: JoinError) -> LabeledError {
+    LabeledError::new(format!("Benchmark task failed: {error}"))
+}
@@ -1,9 +1,20 @@
+use std::{fs, path::PathBuf, sync::Arc, time::Duration};
+
 use anyhow::Context;
-use async_nats::ConnectOptions;
+use async_nats::{AuthError, Client, ConnectOptions, ServerAddr};
+use nkeys::KeyPair;
 use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
-use nu_protocol::{LabeledError, PipelineData, ShellError, Signature, SyntaxShape};
+use nu_protocol::{
+    LabeledError, PipelineData, Record, ShellError, Signature, Spanned, SyntaxShape, Value,
+};
 
-use crate::Nuts;
+use crate::{
+    Nuts,
+    commands::{AUTH_FLAGS, SignatureExt, string_or_list_shape},
+    connections::{Connection, DEFAULT_CONNECTION_NAME},
+    context::{ContextStore, NatsContext},
+    events::ConnectionEvent,
+};
 
 #[derive(Debug)]
 pub(crate) struct Connect;
@@ -17,31 +28,117 @@ impl PluginCommand for Connect {
 
     fn signature(&self) -> Signature {
         Signature::build(self.name())
-            .optional("url", SyntaxShape::String, "Connection URL")
+            .optional(
+                "url",
+                string_or_list_shape(),
+                "Server URL, comma separated server URLs or a list of server URLs. Supports nats://, tls://, ws:// and wss:// URLs",
+            )
+            .switch(
+                "from-input",
+                "Read the server URLs from the pipeline input instead of passing it through",
+                Some('i'),
+            )
+            .switch(
+                "no-randomize",
+                "Connect to the servers in the given order instead of a random one",
+                None,
+            )
+            .auth_flags()
             .named(
-                "user",
-                SyntaxShape::String,
-                "Username or token to authenticate with",
+                "tls-ca",
+                SyntaxShape::Filepath,
+                "CA certificate to verify the server certificate with",
                 None,
             )
             .named(
-                "password",
-                SyntaxShape::String,
-                "Password to authenticate with",
+                "tls-cert",
+                SyntaxShape::Filepath,
+                "Client certificate for mutual TLS",
+                None,
+            )
+            .named(
+                "tls-key",
+                SyntaxShape::Filepath,
+                "Private key of the client certificate for mutual TLS",
+                None,
+            )
+            .switch("tls-required", "Require a TLS connection", None)
+            .switch(
+                "tls-first",
+                "Perform the TLS handshake before receiving the server INFO",
+                None,
+            )
+            .named(
+                "connect-timeout",
+                SyntaxShape::Duration,
+                "Timeout of establishing the connection",
+                None,
+            )
+            .named(
+                "request-timeout",
+                SyntaxShape::Duration,
+                "Default timeout of requests",
+                None,
+            )
+            .named(
+                "max-reconnects",
+                SyntaxShape::Int,
+                "Maximum number of reconnect attempts. 0 means unlimited",
+                None,
+            )
+            .named(
+                "reconnect-delay",
+                SyntaxShape::Duration,
+                "Delay between reconnect attempts",
+                None,
+            )
+            .named(
+                "ping-interval",
+                SyntaxShape::Duration,
+                "Interval of the pings sent to the server",
+                None,
+            )
+            .switch(
+                "retry-on-initial-connect",
+                "Keep retrying in the background if the server is not reachable on connect",
                 None,
             )
             .named(
-                "creds",
+                "client-name",
                 SyntaxShape::String,
-                "Token to authenticate with",
+                "Name of the client reported to the server",
                 None,
             )
             .named(
-                "nkey",
+                "inbox-prefix",
                 SyntaxShape::String,
-                "NKEY to authenticate with",
+                "Prefix of the inbox subjects used for replies",
+                None,
+            )
+            .switch(
+                "no-echo",
+                "Do not receive messages published by this connection",
+                None,
+            )
+            .named(
+                "subscription-capacity",
+                SyntaxShape::Int,
+                "Number of messages buffered for each subscription",
                 None,
             )
+            .named(
+                "name",
+                SyntaxShape::String,
+                "Name to register the connection under. Defaults to the default connection",
+                Some('n'),
+            )
+            .named(
+                "context",
+                SyntaxShape::String,
+                "Name of a NATS CLI context to read the connection settings from. Defaults to the context selected with `nats context select` if no URL is given",
+                Some('c'),
+            )
+            .switch("default", "Make this connection the default one", Some('d'))
     }
 
     fn description(&self) -> &str {
@@ -55,14 +152,681 @@ impl PluginCommand for Connect {
         call: &EvaluatedCall,
         input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
-        let url: String = call.opt(0)?.unwrap_or_else(|| String::from("localhost"));
-        let user = get_flag_or_env_var("user", "NATS_USER", call, engine)?;
-        let password = get_flag_or_env_var("password", "NATS_PASSWORD", call, engine)?;
-        let credentials = get_flag_or_env_var("creds", "NATS_CREDS", call, engine)?;
-        let nkey = get_flag_or_env_var("nkey", "NATS_NKEY", call, engine)?;
+        let name: String = match call.get_flag("name")? {
+            Some(name) => name,
+            None => plugin
+                .connections
+                .read()
+                .unwrap()
+                .default_name()
+                .unwrap_or(DEFAULT_CONNECTION_NAME)
+                .to_owned(),
+        };
+        let make_default = call.has_flag("default")?;
+        let mut settings = ConnectSettings::from_call(call, engine)?;
+        let output = if call.has_flag("from-input")? {
+            if !call.positional.is_empty() {
+                return Err(LabeledError::new("Conflicting server URLs").with_label(
+                    "`url` argument cannot be used together with `--from-input`",
+                    call.head,
+                ));
+            }
+            settings.urls = urls_from_value(input.into_value(call.head)?)?;
+            PipelineData::Empty
+        } else {
+            input
+        };
+        open_connection(plugin, name, settings, make_default)?;
+        plugin.lifecycle.touch(engine)?;
+
+        Ok(output)
+    }
+}
+
+/// Keys accepted in `$env.config.plugins.nuts`. Besides the connection settings
+/// `connections` holds the settings of named connections.
+const CONFIG_KEYS: &[&str] = &[
+    "url",
+    "user",
+    "password",
+    "creds",
+    "nkey",
+    "token",
+    "jwt",
+    "seed",
+    "tls_ca",
+    "tls_cert",
+    "tls_key",
+    "tls_required",
+    "tls_first",
+    "inbox_prefix",
+    "connect_timeout",
+    "request_timeout",
+    "max_reconnects",
+    "reconnect_delay",
+    "ping_interval",
+    "retry_on_initial_connect",
+    "client_name",
+    "no_echo",
+    "subscription_capacity",
+    "no_randomize",
+    "context",
+    "connections",
+    "idle_timeout",
+];
+
+/// Keys of the plugin configuration that make commands connect lazily. The other keys only
+/// tune a connection, so a configuration like `{ idle_timeout: 30min }` does not connect.
+const LAZY_CONNECT_KEYS: &[&str] = &[
+    "url", "context", "user", "password", "creds", "nkey", "token", "jwt", "seed", "tls_ca",
+    "tls_cert", "tls_key",
+];
+
+/// Reads the time after the last activity when the connections are closed and the plugin
+/// can be stopped by the garbage collector again. Returns `None` if it is not configured.
+pub(crate) fn idle_timeout_from_config(
+    engine: &EngineInterface,
+) -> Result<Option<Duration>, LabeledError> {
+    let Some(config) = engine.get_plugin_config()? else {
+        return Ok(None);
+    };
+    config
+        .as_record()
+        .ok()
+        .and_then(|config| config.get("idle_timeout"))
+        .cloned()
+        .map(value_to_duration)
+        .transpose()
+}
+
+/// Connects using the default or named connection settings of the plugin configuration.
+/// Returns `None` if the configuration has no connection settings for the connection.
+pub(crate) fn connect_from_config(
+    plugin: &Nuts,
+    engine: &EngineInterface,
+    name: Option<&Spanned<String>>,
+) -> Result<Option<Client>, LabeledError> {
+    let Some(config) = engine.get_plugin_config()? else {
+        return Ok(None);
+    };
+    let span = config.span();
+    let config = config.into_record().map_err(|_| {
+        LabeledError::new("Invalid plugin configuration")
+            .with_label("`$env.config.plugins.nuts` must be a record", span)
+    })?;
+    let (name, settings) = match name {
+        Some(name) => {
+            let Some(connection) = config
+                .get("connections")
+                .and_then(|connections| connections.as_record().ok())
+                .and_then(|connections| connections.get(&name.item))
+            else {
+                return Ok(None);
+            };
+            let settings = connection.as_record().map_err(|_| {
+                LabeledError::new(format!("Invalid settings for connection `{}`", name.item))
+                    .with_label("expected a record", connection.span())
+            })?;
+            (name.item.clone(), settings)
+        }
+        None => (DEFAULT_CONNECTION_NAME.to_owned(), &config),
+    };
+    if !settings
+        .columns()
+        .any(|key| LAZY_CONNECT_KEYS.contains(&key.as_str()))
+    {
+        return Ok(None);
+    }
+    let settings = ConnectSettings::from_config(settings, engine)?;
+    // Connecting to localhost by accident would hide that the connection is missing
+    if settings.urls.is_empty() {
+        return Err(LabeledError::new(format!(
+            "Missing server URL for connection `{name}` in the plugin configuration"
+        ))
+        .with_label("no `url` or `context` with a URL", span)
+        .with_help("add a `url` to the plugin configuration or call `nuts connect` first"));
+    }
+    open_connection(plugin, name, settings, false).map(Some)
+}
+
+/// Opens a connection and registers it under `name`
+fn open_connection(
+    plugin: &Nuts,
+    name: String,
+    settings: ConnectSettings,
+    make_default: bool,
+) -> Result<Client, LabeledError> {
+    let urls = settings.urls_or_default();
+    let options = settings.clone().into_options()?.event_callback({
+        let events = plugin.events.clone();
+        let name = name.clone();
+        move |event| {
+            let events = events.clone();
+            let name = name.clone();
+            async move { events.push(ConnectionEvent::new(name, event)) }
+        }
+    });
+    let client = connect(plugin, urls.clone(), options)?;
+    plugin.connections.write().unwrap().insert(
+        name,
+        Connection {
+            client: client.clone(),
+            url: urls.join(","),
+            settings: Arc::new(settings),
+        },
+        make_default,
+    );
+    Ok(client)
+}
+
+/// Opens a temporary connection that is not registered if the call has the `--server` flag.
+/// The authentication flags only apply to temporary connections.
+pub(crate) fn connect_from_server_flag(
+    plugin: &Nuts,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<Option<Client>, LabeledError> {
+    server_flag_settings(engine, call)?
+        .map(|settings| {
+            let urls = settings.urls_or_default();
+            connect(plugin, urls, settings.into_options()?)
+        })
+        .transpose()
+}
+
+/// Opens an unregistered connection with the settings of the connection selected by the call,
+/// for commands like `nuts bench` that need several connections to the same server.
+/// The selected connection must be open already, e.g. by calling [`Nuts::client`] first.
+pub(crate) fn connect_like(
+    plugin: &Nuts,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<Client, LabeledError> {
+    let settings = match server_flag_settings(engine, call)? {
+        Some(settings) => settings,
+        None => {
+            let name: Option<Spanned<String>> = call.get_flag("connection")?;
+            ConnectSettings::clone(
+                &plugin
+                    .connections
+                    .read()
+                    .unwrap()
+                    .get(name.as_ref())?
+                    .settings,
+            )
+        }
+    };
+    let urls = settings.urls_or_default();
+    connect(plugin, urls, settings.into_options()?)
+}
+
+/// Reads the settings of a temporary connection if the call has the `--server` flag
+fn server_flag_settings(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<Option<ConnectSettings>, LabeledError> {
+    let Some(server) = call.get_flag_value("server") else {
+        if let Some((flag, _)) = call
+            .named
+            .iter()
+            .find(|(flag, _)| AUTH_FLAGS.contains(&flag.item.as_str()))
+        {
+            return Err(LabeledError::new("Missing `--server`")
+                .with_label("requires `--server`", flag.span)
+                .with_help("registered connections use the authentication they were opened with"));
+        }
+        return Ok(None);
+    };
+    Ok(Some(ConnectSettings {
+        urls: urls_from_value(server)?,
+        ..ConnectSettings::from_auth_flags(call, Some(engine))?
+    }))
+}
+
+fn connect(
+    plugin: &Nuts,
+    urls: Vec<String>,
+    options: ConnectOptions,
+) -> Result<Client, LabeledError> {
+    plugin
+        .runtime
+        .block_on(async move {
+            async_nats::connect_with_options(urls, options)
+                .await
+                .context("Failed to connect to NATS server")
+        })
+        .map_err(|error| LabeledError::new(error.to_string()))
+}
+
+/// Settings used to open a connection, gathered from flags, environment variables,
+/// the plugin configuration or a NATS context
+#[derive(Debug, Default, Clone)]
+pub(crate) struct ConnectSettings {
+    urls: Vec<String>,
+    user: Option<String>,
+    password: Option<String>,
+    creds: Option<String>,
+    nkey: Option<String>,
+    token: Option<String>,
+    jwt: Option<String>,
+    seed: Option<String>,
+    tls_ca: Option<Spanned<PathBuf>>,
+    tls_cert: Option<Spanned<PathBuf>>,
+    tls_key: Option<Spanned<PathBuf>>,
+    tls_required: bool,
+    tls_first: bool,
+    inbox_prefix: Option<String>,
+    connect_timeout: Option<Duration>,
+    request_timeout: Option<Duration>,
+    max_reconnects: Option<usize>,
+    reconnect_delay: Option<Duration>,
+    ping_interval: Option<Duration>,
+    retry_on_initial_connect: bool,
+    client_name: Option<String>,
+    no_echo: bool,
+    subscription_capacity: Option<usize>,
+    no_randomize: bool,
+}
+
+impl ConnectSettings {
+    fn from_call(call: &EvaluatedCall, engine: &EngineInterface) -> Result<Self, LabeledError> {
+        let context_name = match call.get_flag::<Spanned<String>>("context")? {
+            Some(name) => Some(name),
+            // Like the NATS CLI, fall back to the context selected with `nats context select`
+            None if call.positional.is_empty() && !call.has_flag("from-input")? => {
+                ContextStore::new(engine)
+                    .ok()
+                    .and_then(|store| store.selected())
+                    .map(|item| Spanned {
+                        item,
+                        span: call.head,
+                    })
+            }
+            None => None,
+        };
+        let context = context_name
+            .map(|name| Self::from_context_name(&name, engine))
+            .transpose()?;
+        // A context replaces the `NATS_*` environment variables, like it does in the NATS CLI
+        let env = if context.is_none() {
+            Some(engine)
+        } else {
+            None
+        };
+        let current_dir = PathBuf::from(engine.get_current_dir()?);
+        let get_path = |flag: &str, env_var: &str| {
+            let span = call
+                .get_flag_value(flag)
+                .map(|value| value.span())
+                .unwrap_or(call.head);
+            Ok::<_, ShellError>(get_flag_or_env_var(flag, env_var, call, env)?.map(|path| {
+                Spanned {
+                    item: current_dir.join(path),
+                    span,
+                }
+            }))
+        };
+        let settings = Self {
+            urls: call
+                .positional
+                .first()
+                .cloned()
+                .map(urls_from_value)
+                .transpose()?
+                .unwrap_or_default(),
+            tls_ca: get_path("tls-ca", "NATS_CA")?,
+            tls_cert: get_path("tls-cert", "NATS_CERT")?,
+            tls_key: get_path("tls-key", "NATS_KEY")?,
+            tls_required: call.has_flag("tls-required")?,
+            tls_first: call.has_flag("tls-first")?,
+            inbox_prefix: get_flag_or_env_var("inbox-prefix", "NATS_INBOX_PREFIX", call, env)?,
+            connect_timeout: get_flag_or_env_value(
+                "connect-timeout",
+                "NATS_CONNECT_TIMEOUT",
+                call,
+                env,
+            )?
+            .map(value_to_duration)
+            .transpose()?,
+            request_timeout: get_flag_or_env_value(
+                "request-timeout",
+                "NATS_REQUEST_TIMEOUT",
+                call,
+                env,
+            )?
+            .map(value_to_duration)
+            .transpose()?,
+            max_reconnects: get_flag_or_env_value(
+                "max-reconnects",
+                "NATS_MAX_RECONNECTS",
+                call,
+                env,
+            )?
+            .map(value_to_usize)
+            .transpose()?,
+            reconnect_delay: get_flag_or_env_value(
+                "reconnect-delay",
+                "NATS_RECONNECT_DELAY",
+                call,
+                env,
+            )?
+            .map(value_to_duration)
+            .transpose()?,
+            ping_interval: get_flag_or_env_value("ping-interval", "NATS_PING_INTERVAL", call, env)?
+                .map(value_to_duration)
+                .transpose()?,
+            retry_on_initial_connect: get_switch_or_env_var(
+                "retry-on-initial-connect",
+                "NATS_RETRY_ON_INITIAL_CONNECT",
+                call,
+                env,
+            )?,
+            client_name: get_flag_or_env_var("client-name", "NATS_CLIENT_NAME", call, env)?,
+            no_echo: get_switch_or_env_var("no-echo", "NATS_NO_ECHO", call, env)?,
+            subscription_capacity: get_flag_or_env_value(
+                "subscription-capacity",
+                "NATS_SUBSCRIPTION_CAPACITY",
+                call,
+                env,
+            )?
+            .map(value_to_usize)
+            .transpose()?,
+            no_randomize: get_switch_or_env_var("no-randomize", "NATS_NO_RANDOMIZE", call, env)?,
+            ..Self::from_auth_flags(call, env)?
+        };
+        Ok(match context {
+            Some(context) => settings.or(context),
+            None => settings,
+        })
+    }
+
+    /// Reads only the authentication settings, which are shared by `nuts connect` and `--server`.
+    /// The environment variables are ignored if any authentication flag is given.
+    fn from_auth_flags(
+        call: &EvaluatedCall,
+        env: Option<&EngineInterface>,
+    ) -> Result<Self, LabeledError> {
+        let env = if has_auth_flags(call) { None } else { env };
+        Ok(Self {
+            user: get_flag_or_env_var("user", "NATS_USER", call, env)?,
+            password: get_flag_or_env_var("password", "NATS_PASSWORD", call, env)?,
+            creds: get_flag_or_env_var("creds", "NATS_CREDS", call, env)?,
+            nkey: get_flag_or_env_var("nkey", "NATS_NKEY", call, env)?,
+            token: get_flag_or_env_var("token", "NATS_TOKEN", call, env)?,
+            jwt: call.get_flag("jwt")?,
+            seed: call.get_flag("seed")?,
+            ..Default::default()
+        })
+    }
+
+    /// Reads the settings from the plugin configuration record, which uses the names of the
+    /// `nuts connect` flags in snake case
+    fn from_config(config: &Record, engine: &EngineInterface) -> Result<Self, LabeledError> {
+        if let Some((key, value)) = config
+            .iter()
+            .find(|(key, _)| !CONFIG_KEYS.contains(&key.as_str()))
+        {
+            return Err(
+                LabeledError::new(format!("Unknown plugin configuration key `{key}`"))
+                    .with_label("unknown key", value.span())
+                    .with_help(format!("supported keys are {}", CONFIG_KEYS.join(", "))),
+            );
+        }
+        let get_string = |key: &str| {
+            config
+                .get(key)
+                .cloned()
+                .map(|value| value.coerce_into_string())
+                .transpose()
+        };
+        let get_path = |key: &str| {
+            config
+                .get(key)
+                .map(|value| {
+                    Ok::<_, ShellError>(Spanned {
+                        item: PathBuf::from(value.coerce_str()?.as_ref()),
+                        span: value.span(),
+                    })
+                })
+                .transpose()
+        };
+        let get_bool = |key: &str| {
+            config
+                .get(key)
+                .cloned()
+                .map(value_to_bool)
+                .transpose()
+                .map(Option::unwrap_or_default)
+        };
+        let get_duration = |key: &str| config.get(key).cloned().map(value_to_duration).transpose();
+        let get_usize = |key: &str| config.get(key).cloned().map(value_to_usize).transpose();
+        let settings = Self {
+            urls: config
+                .get("url")
+                .cloned()
+                .map(urls_from_value)
+                .transpose()?
+                .unwrap_or_default(),
+            user: get_string("user")?,
+            password: get_string("password")?,
+            creds: get_string("creds")?,
+            nkey: get_string("nkey")?,
+            token: get_string("token")?,
+            jwt: get_string("jwt")?,
+            seed: get_string("seed")?,
+            tls_ca: get_path("tls_ca")?,
+            tls_cert: get_path("tls_cert")?,
+            tls_key: get_path("tls_key")?,
+            tls_required: get_bool("tls_required")?,
+            tls_first: get_bool("tls_first")?,
+            inbox_prefix: get_string("inbox_prefix")?,
+            connect_timeout: get_duration("connect_timeout")?,
+            request_timeout: get_duration("request_timeout")?,
+            max_reconnects: get_usize("max_reconnects")?,
+            reconnect_delay: get_duration("reconnect_delay")?,
+            ping_interval: get_duration("ping_interval")?,
+            retry_on_initial_connect: get_bool("retry_on_initial_connect")?,
+            client_name: get_string("client_name")?,
+            no_echo: get_bool("no_echo")?,
+            subscription_capacity: get_usize("subscription_capacity")?,
+            no_randomize: get_bool("no_randomize")?,
+        };
+        let context = config
+            .get("context")
+            .map(|value| {
+                Self::from_context_name(
+                    &Spanned {
+                        item: value.coerce_string()?,
+                        span: value.span(),
+                    },
+                    engine,
+                )
+            })
+            .transpose()?;
+        Ok(match context {
+            Some(context) => settings.or(context),
+            None => settings,
+        })
+    }
+
+    fn from_context_name(
+        name: &Spanned<String>,
+        engine: &EngineInterface,
+    ) -> Result<Self, LabeledError> {
+        let store = ContextStore::new(engine)?;
+        let context = store.load(name)?;
+        Self::from_context(context, &store, name)
+    }
+
+    fn from_context(
+        context: NatsContext,
+        store: &ContextStore,
+        name: &Spanned<String>,
+    ) -> Result<Self, LabeledError> {
+        let expand_path = |path: String| Spanned {
+            item: store.expand_path(&path),
+            span: name.span,
+        };
+        let read_file = |kind: &str, path: String| {
+            let path = store.expand_path(&path);
+            fs::read_to_string(&path).map_err(|error| {
+                LabeledError::new(format!(
+                    "Failed to read {kind} file of NATS context `{}`",
+                    name.item
+                ))
+                .with_label(format!("{}: {error}", path.display()), name.span)
+            })
+        };
+        Ok(Self {
+            urls: context.url.map(|url| split_urls(&url)).unwrap_or_default(),
+            user: context.user,
+            password: context.password,
+            creds: context
+                .creds
+                .map(|path| read_file("credentials", path))
+                .transpose()?,
+            nkey: context
+                .nkey
+                .map(|path| read_file("nkey", path).map(|seed| seed.trim().to_owned()))
+                .transpose()?,
+            token: context.token,
+            jwt: None,
+            seed: None,
+            tls_ca: context.ca.map(expand_path),
+            tls_cert: context.cert.map(expand_path),
+            tls_key: context.key.map(expand_path),
+            tls_required: false,
+            tls_first: context.tls_first,
+            inbox_prefix: context.inbox_prefix,
+            ..Default::default()
+        })
+    }
+
+    /// Fills the settings that are not set with the ones from `fallback`. The authentication
+    /// settings are taken as a whole from `fallback` only if none of them is set.
+    fn or(self, fallback: Self) -> Self {
+        let auth = if self.has_auth() { &self } else { &fallback };
+        let (user, password, creds, nkey, token, jwt, seed) = (
+            auth.user.clone(),
+            auth.password.clone(),
+            auth.creds.clone(),
+            auth.nkey.clone(),
+            auth.token.clone(),
+            auth.jwt.clone(),
+            auth.seed.clone(),
+        );
+        Self {
+            urls: if self.urls.is_empty() {
+                fallback.urls
+            } else {
+                self.urls
+            },
+            user,
+            password,
+            creds,
+            nkey,
+            token,
+            jwt,
+            seed,
+            tls_ca: self.tls_ca.or(fallback.tls_ca),
+            tls_cert: self.tls_cert.or(fallback.tls_cert),
+            tls_key: self.tls_key.or(fallback.tls_key),
+            tls_required: self.tls_required || fallback.tls_required,
+            tls_first: self.tls_first || fallback.tls_first,
+            inbox_prefix: self.inbox_prefix.or(fallback.inbox_prefix),
+            connect_timeout: self.connect_timeout.or(fallback.connect_timeout),
+            request_timeout: self.request_timeout.or(fallback.request_timeout),
+            max_reconnects: self.max_reconnects.or(fallback.max_reconnects),
+            reconnect_delay: self.reconnect_delay.or(fallback.reconnect_delay),
+            ping_interval: self.ping_interval.or(fallback.ping_interval),
+            retry_on_initial_connect: self.retry_on_initial_connect
+                || fallback.retry_on_initial_connect,
+            client_name: self.client_name.or(fallback.client_name),
+            no_echo: self.no_echo || fallback.no_echo,
+            subscription_capacity: self
+                .subscription_capacity
+                .or(fallback.subscription_capacity),
+            no_randomize: self.no_randomize || fallback.no_randomize,
+        }
+    }
+
+    fn has_auth(&self) -> bool {
+        self.user.is_some()
+            || self.password.is_some()
+            || self.creds.is_some()
+            || self.nkey.is_some()
+            || self.token.is_some()
+            || self.jwt.is_some()
+            || self.seed.is_some()
+    }
+
+    fn urls_or_default(&self) -> Vec<String> {
+        if self.urls.is_empty() {
+            vec![String::from("localhost")]
+        } else {
+            self.urls.clone()
+        }
+    }
 
+    /// Returns an error if more than one authentication method is configured. The settings only
+    /// contain the authentication of a single source, see [`ConnectSettings::or`].
+    fn check_auth_methods(&self) -> Result<(), LabeledError> {
+        let methods = [
+            (
+                "`--user`/`--password`",
+                self.user.is_some() || self.password.is_some(),
+            ),
+            ("`--token`", self.token.is_some()),
+            ("`--creds`", self.creds.is_some()),
+            ("`--nkey`", self.nkey.is_some()),
+            (
+                "`--jwt`/`--seed`",
+                self.jwt.is_some() || self.seed.is_some(),
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(method, is_set)| is_set.then_some(method))
+        .collect::<Vec<&str>>();
+        if methods.len() > 1 {
+            return Err(
+                LabeledError::new("Conflicting authentication methods").with_help(format!(
+                    "use only one of {} as flags, environment variables, plugin configuration or in the context",
+                    methods.join(", ")
+                )),
+            );
+        }
+        Ok(())
+    }
+
+    /// TLS settings only apply to `wss://` WebSocket URLs, so a plain `ws://` URL would silently
+    /// connect without TLS and ignore the certificates
+    fn check_websocket_tls(&self) -> Result<(), LabeledError> {
+        let Some(url) = self.urls.iter().find(|url| url.starts_with("ws://")) else {
+            return Ok(());
+        };
+        let certificate = [&self.tls_ca, &self.tls_cert, &self.tls_key]
+            .into_iter()
+            .flatten()
+            .next();
+        match certificate {
+            Some(path) => Err(LabeledError::new(format!(
+                "TLS certificates are given but `{url}` is an unencrypted WebSocket URL"
+            ))
+            .with_label("only applies to wss:// URLs", path.span)
+            .with_help("use a wss:// URL to connect through a WebSocket with TLS")),
+            None if self.tls_required => Err(LabeledError::new(format!(
+                "TLS is required but `{url}` is an unencrypted WebSocket URL"
+            ))
+            .with_help("use a wss:// URL to connect through a WebSocket with TLS")),
+            None => Ok(()),
+        }
+    }
+
+    fn into_options(self) -> Result<ConnectOptions, LabeledError> {
+        self.check_auth_methods()?;
+        self.check_websocket_tls()?;
         let options = ConnectOptions::new();
-        let options = match (user, password) {
+        let options = match (self.user, self.password) {
             (None, None) => options,
             (Some(user), Some(password)) => options.user_and_password(user, password),
             (Some(_), None) => {
@@ -76,27 +840,186 @@ impl PluginCommand for Connect {
                 ));
             }
         };
-        let options = match credentials {
+        let options = match self.token {
+            Some(token) => options.token(token),
+            None => options,
+        };
+        let options = match self.creds {
             Some(credentials) => options
                 .credentials(&credentials)
                 .map_err(|err| LabeledError::new(err.to_string()))?,
             None => options,
         };
-        let options = match nkey {
+        let options = match self.nkey {
             Some(nkey) => options.nkey(nkey),
             None => options,
         };
-        let client = plugin
-            .runtime
-            .block_on(async move {
-                async_nats::connect_with_options(url, options)
-                    .await
-                    .context("Failed to connect to NATS server")
-            })
-            .map_err(|error| LabeledError::new(error.to_string()))?;
-        *plugin.nats.write().unwrap() = Some(client);
+        let options = match (self.jwt, self.seed) {
+            (None, None) => options,
+            (Some(jwt), Some(seed)) => {
+                let key_pair = Arc::new(KeyPair::from_seed(&seed).map_err(|error| {
+                    LabeledError::new(format!("Invalid `--seed` argument: {error}"))
+                })?);
+                options.jwt(jwt, move |nonce| {
+                    let key_pair = key_pair.clone();
+                    async move { key_pair.sign(&nonce).map_err(AuthError::new) }
+                })
+            }
+            (Some(_), None) => {
+                return Err(LabeledError::new(
+                    "Missing `--seed` argument for JWT authentication",
+                ));
+            }
+            (None, Some(_)) => {
+                return Err(LabeledError::new(
+                    "Missing `--jwt` argument for JWT authentication",
+                ));
+            }
+        };
+        let options = match self.tls_ca {
+            Some(ca) => options.add_root_certificates(check_pem_file("CA certificate", ca)?),
+            None => options,
+        };
+        let options = match (self.tls_cert, self.tls_key) {
+            (None, None) => options,
+            (Some(cert), Some(key)) => options.add_client_certificate(
+                check_pem_file("client certificate", cert)?,
+                check_pem_file("client key", key)?,
+            ),
+            (Some(_), None) => {
+                return Err(LabeledError::new(
+                    "Missing TLS client key for the TLS client certificate",
+                ));
+            }
+            (None, Some(_)) => {
+                return Err(LabeledError::new(
+                    "Missing TLS client certificate for the TLS client key",
+                ));
+            }
+        };
+        let options = options.require_tls(self.tls_required);
+        let options = if self.tls_first {
+            options.tls_first()
+        } else {
+            options
+        };
+        let options = match self.inbox_prefix {
+            Some(prefix) => options.custom_inbox_prefix(prefix),
+            None => options,
+        };
+        let options = match self.connect_timeout {
+            Some(timeout) => options.connection_timeout(timeout),
+            None => options,
+        };
+        let options = match self.request_timeout {
+            Some(timeout) => options.request_timeout(Some(timeout)),
+            None => options,
+        };
+        let options = match self.max_reconnects {
+            Some(max_reconnects) => options.max_reconnects(max_reconnects),
+            None => options,
+        };
+        let options = match self.reconnect_delay {
+            Some(delay) => options.reconnect_delay_callback(move |_| delay),
+            None => options,
+        };
+        let options = match self.ping_interval {
+            Some(interval) => options.ping_interval(interval),
+            None => options,
+        };
+        let options = if self.retry_on_initial_connect {
+            options.retry_on_initial_connect()
+        } else {
+            options
+        };
+        let options = match self.client_name {
+            Some(name) => options.name(name),
+            None => options,
+        };
+        let options = if self.no_echo {
+            options.no_echo()
+        } else {
+            options
+        };
+        let options = if self.no_randomize {
+            options.retain_servers_order()
+        } else {
+            options
+        };
+        let options = match self.subscription_capacity {
+            Some(capacity) => options.subscription_capacity(capacity),
+            None => options,
+        };
+        Ok(options)
+    }
+}
+
+/// Returns whether any of the flags added by [`SignatureExt::auth_flags`] is given
+fn has_auth_flags(call: &EvaluatedCall) -> bool {
+    AUTH_FLAGS
+        .iter()
+        .any(|flag| call.get_flag_value(flag).is_some())
+}
 
-        Ok(input)
+/// Splits a comma separated list of server URLs, as accepted by the official NATS tools
+fn split_urls(urls: &str) -> Vec<String> {
+    urls.split(',')
+        .map(str::trim)
+        .filter(|url| !url.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Collects the server URLs from a string or a list of strings, validating each of them
+pub(crate) fn urls_from_value(value: Value) -> Result<Vec<String>, LabeledError> {
+    let values = match value {
+        Value::List { vals, .. } => vals,
+        value => vec![value],
+    };
+    let mut urls = Vec::new();
+    for value in values {
+        let span = value.span();
+        for url in split_urls(&value.coerce_into_string()?) {
+            url.parse::<ServerAddr>().map_err(|error| {
+                LabeledError::new(format!("Invalid server URL `{url}`"))
+                    .with_label(error.to_string(), span)
+            })?;
+            urls.push(url);
+        }
+    }
+    Ok(urls)
+}
+
+/// Checks that a TLS file can be read and is PEM encoded, since async-nats only loads it
+/// when connecting and reports a failure without the path of the offending file
+fn check_pem_file(kind: &str, path: Spanned<PathBuf>) -> Result<PathBuf, LabeledError> {
+    let content = fs::read(&path.item).map_err(|error| {
+        LabeledError::new(format!("Failed to read TLS {kind}"))
+            .with_label(format!("{}: {error}", path.item.display()), path.span)
+    })?;
+    if !content
+        .windows(b"-----BEGIN ".len())
+        .any(|window| window == b"-----BEGIN ")
+    {
+        return Err(LabeledError::new(format!("Invalid TLS {kind}")).with_label(
+            format!("{} is not a PEM encoded file", path.item.display()),
+            path.span,
+        ));
+    }
+    Ok(path.item)
+}
+
+/// Returns the value of a flag, falling back to an environment variable if an engine is given
+fn get_flag_or_env_value(
+    flag: &str,
+    env_var: impl Into<String>,
+    call: &EvaluatedCall,
+    engine: Option<&EngineInterface>,
+) -> Result<Option<Value>, ShellError> {
+    match (call.get_flag_value(flag), engine) {
+        (Some(value), _) => Ok(Some(value)),
+        (None, Some(engine)) => engine.get_env_var(env_var),
+        (None, None) => Ok(None),
     }
 }
 
@@ -104,14 +1027,149 @@ fn get_flag_or_env_var(
     flag: &str,
     env_var: impl Into<String>,
     call: &EvaluatedCall,
-    engine: &EngineInterface,
+    engine: Option<&EngineInterface>,
 ) -> Result<Option<String>, ShellError> {
-    let value: Option<String> = match call.get_flag(flag)? {
-        Some(nkey) => Some(nkey),
-        None => engine
-            .get_env_var(env_var)?
-            .map(|value| value.coerce_into_string())
-            .transpose()?,
+    get_flag_or_env_value(flag, env_var, call, engine)?
+        .map(|value| value.coerce_into_string())
+        .transpose()
+}
+
+/// Returns whether a switch is set, falling back to a boolean environment variable
+/// if an engine is given
+fn get_switch_or_env_var(
+    flag: &str,
+    env_var: impl Into<String>,
+    call: &EvaluatedCall,
+    engine: Option<&EngineInterface>,
+) -> Result<bool, LabeledError> {
+    if call.has_flag(flag)? {
+        return Ok(true);
+    }
+    let Some(value) = engine
+        .map(|engine| engine.get_env_var(env_var))
+        .transpose()?
+        .flatten()
+    else {
+        return Ok(false);
+    };
+    value_to_bool(value)
+}
+
+/// Converts a boolean value or a string like `true`, `yes` or `1` into a [`bool`]
+fn value_to_bool(value: Value) -> Result<bool, LabeledError> {
+    let span = value.span();
+    match value {
+        Value::Bool { val, .. } => Ok(val),
+        Value::String { val, .. } => match val.to_lowercase().as_str() {
+            "" | "0" | "false" | "no" => Ok(false),
+            "1" | "true" | "yes" => Ok(true),
+            _ => Err(LabeledError::new(format!("Invalid boolean `{val}`"))
+                .with_label("expected true or false", span)),
+        },
+        value => Err(LabeledError::new("Invalid boolean").with_label(
+            format!("expected a boolean, found {}", value.get_type()),
+            span,
+        )),
+    }
+}
+
+/// Converts a duration value or a string like `500ms` or `5sec` into a [`Duration`]
+pub(crate) fn value_to_duration(value: Value) -> Result<Duration, LabeledError> {
+    let span = value.span();
+    let nanos = match value {
+        Value::Duration { val, .. } => val,
+        Value::String { val, .. } => parse_duration(&val).ok_or_else(|| {
+            LabeledError::new(format!("Invalid duration `{val}`"))
+                .with_label("expected a duration like 500ms or 5sec", span)
+        })?,
+        value => {
+            return Err(LabeledError::new("Invalid duration").with_label(
+                format!("expected a duration, found {}", value.get_type()),
+                span,
+            ));
+        }
+    };
+    u64::try_from(nanos)
+        .map(Duration::from_nanos)
+        .map_err(|_| LabeledError::new("Invalid duration").with_label("must not be negative", span))
+}
+
+/// Parses a duration in Nushell syntax into nanoseconds
+fn parse_duration(duration: &str) -> Option<i64> {
+    let duration = duration.trim();
+    let unit_start = duration.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+    let (number, unit) = duration.split_at(unit_start);
+    let multiplier: f64 = match unit.trim() {
+        "ns" => 1.0,
+        "us" | "µs" => 1e3,
+        "ms" => 1e6,
+        "sec" | "s" => 1e9,
+        "min" | "m" => 60e9,
+        "hr" | "h" => 3600e9,
+        "day" | "d" => 86400e9,
+        "wk" => 604800e9,
+        _ => return None,
     };
-    Ok(value)
+    Some((number.parse::<f64>().ok()? * multiplier) as i64)
+}
+
+fn value_to_usize(value: Value) -> Result<usize, LabeledError> {
+    let span = value.span();
+    let int = match value {
+        Value::String { val, .. } => val.trim().parse::<i64>().map_err(|_| {
+            LabeledError::new(format!("Invalid number `{val}`"))
+                .with_label("expected an integer", span)
+        })?,
+        value => value.as_int()?,
+    };
+    usize::try_from(int)
+        .map_err(|_| LabeledError::new("Invalid number").with_label("must not be negative", span))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms"), Some(500_000_000));
+        assert_eq!(parse_duration("5sec"), Some(5_000_000_000));
+        assert_eq!(parse_duration(" 2 min "), Some(120_000_000_000));
+        assert_eq!(parse_duration("1.5s"), Some(1_500_000_000));
+        assert_eq!(parse_duration(".5s"), Some(500_000_000));
+        assert_eq!(parse_duration("10us"), Some(10_000));
+        assert_eq!(parse_duration("1wk"), Some(604_800_000_000_000));
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        assert_eq!(parse_duration("500"), None);
+        assert_eq!(parse_duration("ms"), None);
+        assert_eq!(parse_duration("5 years"), None);
+        assert_eq!(parse_duration("1.2.3s"), None);
+    }
+
+    #[test]
+    fn converts_duration_values() {
+        assert_eq!(
+            value_to_duration(Value::test_string("250ms")).unwrap(),
+            Duration::from_millis(250)
+        );
+        assert_eq!(
+            value_to_duration(Value::test_duration(1_000)).unwrap(),
+            Duration::from_micros(1)
+        );
+        assert!(value_to_duration(Value::test_duration(-1)).is_err());
+        assert!(value_to_duration(Value::test_int(5)).is_err());
+    }
+
+    #[test]
+    fn splits_comma_separated_urls() {
+        assert_eq!(
+            split_urls("nats://a:4222, nats://b:4222,,nats://c:4222 "),
+            ["nats://a:4222", "nats://b:4222", "nats://c:4222"]
+        );
+        assert_eq!(split_urls("nats://a:4222"), ["nats://a:4222"]);
+        assert!(split_urls(" , ").is_empty());
+    }
 }
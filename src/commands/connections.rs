@@ -0,0 +1,83 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Example, IntoValue, LabeledError, PipelineData, Record, Signature, Span, Type, Value,
+};
+
+use crate::Nuts;
+
+#[derive(Debug)]
+pub(crate) struct ListConnections;
+
+impl PluginCommand for ListConnections {
+    type Plugin = Nuts;
+
+    fn name(&self) -> &str {
+        "nuts connections"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name()).input_output_type(Type::Nothing, Type::table())
+    }
+
+    fn description(&self) -> &str {
+        "List the registered connections"
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        vec!["nats", "connection", "list"]
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            example: "nuts connections",
+            description: "List the registered connections",
+            result: Some(
+                [Record::from_iter([
+                    ("name".to_owned(), "default".into_value(Span::unknown())),
+                    ("default".to_owned(), true.into_value(Span::unknown())),
+                    ("url".to_owned(), "localhost".into_value(Span::unknown())),
+                    ("state".to_owned(), "connected".into_value(Span::unknown())),
+                ])
+                .into_value(Span::unknown())]
+                .into_value(Span::unknown()),
+            ),
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let connections = plugin.connections.read().unwrap();
+        let default_name = connections.default_name();
+        let rows = connections
+            .iter()
+            .map(|(name, connection)| {
+                Record::from_iter([
+                    ("name".to_owned(), name.as_str().into_value(call.head)),
+                    (
+                        "default".to_owned(),
+                        (default_name == Some(name.as_str())).into_value(call.head),
+                    ),
+                    (
+                        "url".to_owned(),
+                        connection.url.as_str().into_value(call.head),
+                    ),
+                    (
+                        "state".to_owned(),
+                        connection
+                            .client
+                            .connection_state()
+                            .to_string()
+                            .into_value(call.head),
+                    ),
+                ])
+                .into_value(call.head)
+            })
+            .collect::<Vec<Value>>();
+        Ok(PipelineData::Value(rows.into_value(call.head), None))
+    }
+}
@@ -0,0 +1,87 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Example, IntoValue, LabeledError, PipelineData, Record, Signature, Span, Spanned, Type, Value,
+};
+
+use crate::{Nuts, context::ContextStore};
+
+#[derive(Debug)]
+pub(crate) struct List;
+
+impl PluginCommand for List {
+    type Plugin = Nuts;
+
+    fn name(&self) -> &str {
+        "nuts context list"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name()).input_output_type(Type::Nothing, Type::table())
+    }
+
+    fn description(&self) -> &str {
+        "List the NATS CLI contexts"
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        vec!["nats", "context", "list"]
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            example: "nuts context list",
+            description: "List the contexts saved with `nats context`",
+            result: Some(
+                [Record::from_iter([
+                    ("selected".to_owned(), true.into_value(Span::unknown())),
+                    ("name".to_owned(), "dev".into_value(Span::unknown())),
+                    (
+                        "description".to_owned(),
+                        "Development cluster".into_value(Span::unknown()),
+                    ),
+                    (
+                        "url".to_owned(),
+                        "nats://localhost:4222".into_value(Span::unknown()),
+                    ),
+                ])
+                .into_value(Span::unknown())]
+                .into_value(Span::unknown()),
+            ),
+        }]
+    }
+
+    fn run(
+        &self,
+        _plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let store = ContextStore::new(engine)?;
+        let selected = store.selected();
+        let rows = store
+            .names()?
+            .into_iter()
+            .map(|name| {
+                let context = store.load(&Spanned {
+                    item: name.clone(),
+                    span: call.head,
+                })?;
+                Ok(Record::from_iter([
+                    (
+                        "selected".to_owned(),
+                        (selected.as_ref() == Some(&name)).into_value(call.head),
+                    ),
+                    ("name".to_owned(), name.into_value(call.head)),
+                    (
+                        "description".to_owned(),
+                        context.description.into_value(call.head),
+                    ),
+                    ("url".to_owned(), context.url.into_value(call.head)),
+                ])
+                .into_value(call.head))
+            })
+            .collect::<Result<Vec<Value>, LabeledError>>()?;
+        Ok(PipelineData::Value(rows.into_value(call.head), None))
+    }
+}
@@ -0,0 +1,3 @@
+pub(crate) mod list;
+
+pub(crate) use list::List;
@@ -0,0 +1,79 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{Example, LabeledError, PipelineData, Signature, Spanned, SyntaxShape};
+
+use crate::{Nuts, connections};
+
+#[derive(Debug)]
+pub(crate) struct Disconnect;
+
+impl PluginCommand for Disconnect {
+    type Plugin = Nuts;
+
+    fn name(&self) -> &str {
+        "nuts disconnect"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .named(
+                "name",
+                SyntaxShape::String,
+                "Name of the connection to close. Defaults to the default connection",
+                Some('n'),
+            )
+            .switch(
+                "drain",
+                "Drain subscriptions and flush pending publishes, and wait until the connection is closed",
+                Some('d'),
+            )
+    }
+
+    fn description(&self) -> &str {
+        "Disconnect from a NATS server"
+    }
+
+    fn extra_description(&self) -> &str {
+        "Without `--drain` the connection is removed from the registry, but stays open until the running subscriptions and watches using it end."
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        vec!["nats", "disconnect", "close", "drain"]
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                example: "nuts disconnect",
+                description: "Close the default connection",
+                result: None,
+            },
+            Example {
+                example: "nuts disconnect --name staging --drain",
+                description: "Drain and close a named connection",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let name: Option<Spanned<String>> = call.get_flag("name")?;
+        let drain = call.has_flag("drain")?;
+        let connection = plugin.connections.write().unwrap().remove(name.as_ref())?;
+
+        if drain {
+            plugin
+                .runtime
+                .block_on(connections::drain(&connection.client))
+                .map_err(|error| LabeledError::new(error.to_string()))?;
+        }
+        plugin.lifecycle.touch(engine)?;
+
+        Ok(input)
+    }
+}
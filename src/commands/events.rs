@@ -0,0 +1,126 @@
+use log::info;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Example, IntoValue, LabeledError, ListStream, PipelineData, Signals, Signature, Type, Value,
+};
+use tokio::{
+    select,
+    sync::{broadcast::error::RecvError, mpsc},
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::Nuts;
+
+#[derive(Debug)]
+pub(crate) struct ListEvents;
+
+impl PluginCommand for ListEvents {
+    type Plugin = Nuts;
+
+    fn name(&self) -> &str {
+        "nuts events"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .switch(
+                "follow",
+                "Keep streaming new events until interrupted",
+                Some('f'),
+            )
+            .input_output_type(Type::Nothing, Type::table())
+    }
+
+    fn description(&self) -> &str {
+        "List connection events like reconnects, disconnects and slow consumers"
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        vec!["nats", "events", "reconnect", "disconnect"]
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                example: "nuts events",
+                description: "List the recent connection events",
+                result: None,
+            },
+            Example {
+                example: "nuts events --follow | where kind == disconnected",
+                description: "Watch for disconnects",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let follow = call.has_flag("follow")?;
+        let span = call.head;
+        let (history, mut receiver) = plugin.events.subscribe();
+        if !follow {
+            let events = history
+                .into_iter()
+                .map(|event| event.into_value(span))
+                .collect::<Vec<Value>>();
+            return Ok(PipelineData::Value(events.into_value(span), None));
+        }
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        for event in history {
+            tx.send(event)
+                .expect("Failed to send event through channel");
+        }
+        let stream_guard = plugin.lifecycle.activity(engine)?;
+        plugin.runtime.spawn({
+            let engine = engine.clone();
+            async move {
+                let _stream_guard = stream_guard;
+                let cancellation = CancellationToken::new();
+                let _signal_guard = engine
+                    .register_signal_handler(Box::new({
+                        let cancellation = cancellation.clone();
+                        move |_| {
+                            info!("Cancel");
+                            cancellation.cancel();
+                        }
+                    }))
+                    .expect("Failed to register signal handler");
+                loop {
+                    select! {
+                        _ = cancellation.cancelled() => {
+                            break;
+                        }
+                        _ = tx.closed() => {
+                            break;
+                        }
+                        event = receiver.recv() => match event {
+                            Ok(event) => {
+                                if tx.send(event).is_err() {
+                                    break;
+                                }
+                            }
+                            Err(RecvError::Lagged(_)) => continue,
+                            Err(RecvError::Closed) => break,
+                        }
+                    };
+                }
+            }
+        });
+
+        let handle = plugin.runtime.handle().clone();
+        let stream_iter = std::iter::repeat_with(move || handle.block_on(rx.recv()))
+            .map_while(move |event| event.map(|event| event.into_value(span)));
+
+        Ok(PipelineData::ListStream(
+            ListStream::new(stream_iter, span, Signals::empty()),
+            None,
+        ))
+    }
+}
@@ -0,0 +1,3 @@
+pub(crate) mod publish;
+
+pub(crate) use publish::Publish;
@@ -0,0 +1,229 @@
+use async_nats::{
+    header::NATS_MESSAGE_ID,
+    jetstream::{self, context::Publish as JetStreamPublish},
+};
+use futures::{StreamExt, TryStreamExt, stream};
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Example, IntoValue, LabeledError, PipelineData, Record, Signature, Span, Spanned, SyntaxShape,
+    Type, Value,
+};
+
+use crate::{
+    Nuts,
+    commands::{self, SignatureExt, publish::DEFAULT_CONCURRENCY},
+    message::OutgoingMessage,
+};
+
+#[derive(Debug)]
+pub(crate) struct Publish;
+
+/// Expectations checked by the server before the message is stored
+#[derive(Debug, Default)]
+struct Expectations {
+    msg_id: Option<String>,
+    stream: Option<String>,
+    last_sequence: Option<u64>,
+    last_subject_sequence: Option<u64>,
+}
+
+impl PluginCommand for Publish {
+    type Plugin = Nuts;
+
+    fn name(&self) -> &str {
+        "nuts js pub"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .optional(
+                "subject",
+                SyntaxShape::String,
+                "Subject to publish to. Can be omitted if every input record has a subject field",
+            )
+            .named(
+                "msg-id",
+                SyntaxShape::String,
+                "Message ID used by the stream to detect duplicates. Messages with a Nats-Msg-Id header keep their own ID. Only for a single message",
+                None,
+            )
+            .named(
+                "expect-stream",
+                SyntaxShape::String,
+                "Only store the message if the subject belongs to this stream",
+                None,
+            )
+            .named(
+                "expect-last-seq",
+                SyntaxShape::Int,
+                "Only store the message if this is the last sequence of the stream. Only for a single message",
+                None,
+            )
+            .named(
+                "expect-last-subject-seq",
+                SyntaxShape::Int,
+                "Only store the message if this is the last sequence of the subject in the stream. Only for a single message",
+                None,
+            )
+            .connection_flags()
+            .input_output_types(commands::Publish::input_output_types(
+                Type::record(),
+                Type::table(),
+            ))
+    }
+
+    fn description(&self) -> &str {
+        "Publish to a JetStream stream and wait for the acknowledgement"
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        vec!["nats", "jetstream", "js", "pub", "publish", "ack"]
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                example: "'my message' | nuts js pub orders.new",
+                description: "Publish a message and return the acknowledgement of the stream",
+                result: None,
+            },
+            Example {
+                example: "'my message' | nuts js pub orders.new --msg-id order-1 --expect-stream ORDERS",
+                description: "Publish a message once to the ORDERS stream",
+                result: None,
+            },
+            Example {
+                example: "[{headers: {Nats-Msg-Id: order-1}, payload: 'order 1'}, {headers: {Nats-Msg-Id: order-2}, payload: 'order 2'}] | nuts js pub orders.new",
+                description: "Publish messages with their own message IDs",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let subject: Option<String> = call.opt(0)?;
+        let expectations = Expectations {
+            msg_id: call.get_flag("msg-id")?,
+            stream: call.get_flag("expect-stream")?,
+            last_sequence: get_sequence_flag(call, "expect-last-seq")?,
+            last_subject_sequence: get_sequence_flag(call, "expect-last-subject-seq")?,
+        };
+        if matches!(
+            input,
+            PipelineData::Value(Value::List { .. }, ..) | PipelineData::ListStream(..)
+        ) {
+            Self::check_list_flags(call)?;
+        }
+        let span = call.head;
+        let client = plugin.client(engine, call)?;
+        let jetstream = jetstream::new(client.clone());
+        let output = plugin.runtime.block_on(async {
+            let publish = |value| {
+                Self::publish_value(&jetstream, subject.as_deref(), &expectations, value, span)
+            };
+            let output = match input {
+                PipelineData::Value(Value::List { vals, .. }, ..) => stream::iter(vals)
+                    .map(publish)
+                    .buffered(DEFAULT_CONCURRENCY)
+                    .try_collect::<Vec<Value>>()
+                    .await?
+                    .into_value(span),
+                PipelineData::ListStream(list_stream, ..) => stream::iter(list_stream)
+                    .map(publish)
+                    .buffered(DEFAULT_CONCURRENCY)
+                    .try_collect::<Vec<Value>>()
+                    .await?
+                    .into_value(span),
+                PipelineData::Value(value, ..) => publish(value).await?,
+                _ => Value::nothing(span),
+            };
+            client.close().await?;
+            Ok::<Value, LabeledError>(output)
+        })?;
+        Ok(PipelineData::Value(output, None))
+    }
+}
+
+impl Publish {
+    /// The rows of a list are published concurrently, so a single message ID would deduplicate
+    /// every row after the first one and at most one row could match an expected last sequence
+    fn check_list_flags(call: &EvaluatedCall) -> Result<(), LabeledError> {
+        for (flag, _) in &call.named {
+            let help = match flag.item.as_str() {
+                "msg-id" => "set a Nats-Msg-Id header on every row to deduplicate the rows",
+                "expect-last-seq" | "expect-last-subject-seq" => {
+                    "publish the messages one at a time to check the last sequence of each"
+                }
+                _ => continue,
+            };
+            return Err(LabeledError::new(format!(
+                "`--{}` cannot be used with list input",
+                flag.item
+            ))
+            .with_label("only applies to a single message", flag.span)
+            .with_help(help));
+        }
+        Ok(())
+    }
+
+    async fn publish_value(
+        jetstream: &jetstream::Context,
+        subject: Option<&str>,
+        expectations: &Expectations,
+        value: Value,
+        span: Span,
+    ) -> Result<Value, LabeledError> {
+        let value_span = value.span();
+        let message = OutgoingMessage::from_value(value)?;
+        let subject = message.subject_or(subject, value_span)?;
+
+        let mut publish = JetStreamPublish::build().payload(message.payload.into());
+        let mut has_msg_id = false;
+        if let Some(headers) = message.headers {
+            has_msg_id = headers.get(NATS_MESSAGE_ID).is_some();
+            publish = publish.headers(headers);
+        }
+        if let (Some(msg_id), false) = (&expectations.msg_id, has_msg_id) {
+            publish = publish.message_id(msg_id);
+        }
+        if let Some(stream) = &expectations.stream {
+            publish = publish.expected_stream(stream);
+        }
+        if let Some(sequence) = expectations.last_sequence {
+            publish = publish.expected_last_sequence(sequence);
+        }
+        if let Some(sequence) = expectations.last_subject_sequence {
+            publish = publish.expected_last_subject_sequence(sequence);
+        }
+
+        let ack = async { jetstream.send_publish(subject, publish).await?.await }
+            .await
+            .map_err(|error| {
+                LabeledError::new("Failed to publish to JetStream")
+                    .with_label(error.to_string(), value_span)
+            })?;
+        Ok(Record::from_iter([
+            ("stream".to_owned(), ack.stream.into_value(span)),
+            ("seq".to_owned(), Value::int(ack.sequence as i64, span)),
+            ("duplicate".to_owned(), ack.duplicate.into_value(span)),
+        ])
+        .into_value(span))
+    }
+}
+
+fn get_sequence_flag(call: &EvaluatedCall, name: &str) -> Result<Option<u64>, LabeledError> {
+    let sequence: Option<Spanned<i64>> = call.get_flag(name)?;
+    sequence
+        .map(|Spanned { item, span }| {
+            u64::try_from(item).map_err(|_| {
+                LabeledError::new("Invalid sequence").with_label("must not be negative", span)
+            })
+        })
+        .transpose()
+}
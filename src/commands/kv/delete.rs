@@ -5,7 +5,7 @@ use futures::future;
 use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
 use nu_protocol::{Example, LabeledError, PipelineData, Signature, SyntaxShape, Type, Value};
 
-use crate::Nuts;
+use crate::{Nuts, commands::SignatureExt};
 
 pub(crate) struct Delete;
 
@@ -23,6 +23,7 @@ impl PluginCommand for Delete {
                 SyntaxShape::String,
                 "Bucket to delete or delete key from",
             )
+            .connection_flags()
             .input_output_types(vec![
                 (Type::Nothing, Type::Nothing),
                 (Type::String, Type::Nothing),
@@ -61,51 +62,37 @@ impl PluginCommand for Delete {
     fn run(
         &self,
         plugin: &Self::Plugin,
-        _engine: &EngineInterface,
+        engine: &EngineInterface,
         call: &EvaluatedCall,
         input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         let bucket: String = call.req(0)?;
-        let client = plugin.nats.read().unwrap();
-        match client.as_ref() {
-            Some(client) => {
-                plugin.runtime.block_on({
-                    let client = client.clone();
-                    async move {
-                        let jetstream = jetstream::new(client);
-                        match input {
-                            PipelineData::Empty => {
-                                jetstream
-                                    .delete_key_value(bucket)
-                                    .await
-                                    .map_err(|error| LabeledError::new(error.to_string()))?;
-                            }
-                            PipelineData::Value(value, ..) => {
-                                Self::delete_value(&bucket, jetstream, value).await?;
-                            }
-                            PipelineData::ListStream(list_stream, ..) => {
-                                future::try_join_all(
-                                    list_stream.into_iter().map(|value| {
-                                        let bucket = bucket.clone();
-                                        let jetstream = jetstream.clone();
-                                        async move {
-                                            Self::delete_value(&bucket, jetstream, value).await
-                                        }
-                                    }),
-                                )
-                                .await?;
-                            }
-                            _ => (),
-                        }
-                        Ok::<(), LabeledError>(())
-                    }
-                })?;
-                Ok(PipelineData::Empty)
+        let client = plugin.client(engine, call)?;
+        plugin.runtime.block_on(async move {
+            let jetstream = jetstream::new(client.clone());
+            match input {
+                PipelineData::Empty => {
+                    jetstream
+                        .delete_key_value(bucket)
+                        .await
+                        .map_err(|error| LabeledError::new(error.to_string()))?;
+                }
+                PipelineData::Value(value, ..) => {
+                    Self::delete_value(&bucket, jetstream, value).await?;
+                }
+                PipelineData::ListStream(list_stream, ..) => {
+                    future::try_join_all(list_stream.into_iter().map(|value| {
+                        let bucket = bucket.clone();
+                        let jetstream = jetstream.clone();
+                        async move { Self::delete_value(&bucket, jetstream, value).await }
+                    }))
+                    .await?;
+                }
+                _ => (),
             }
-            None => Err(LabeledError::new(
-                "Not connected to NATS server. Call `nuts connect` first",
-            )),
-        }
+            client.close().await
+        })?;
+        Ok(PipelineData::Empty)
     }
 }
 
@@ -4,7 +4,7 @@ use nu_protocol::{
     IntoValue, LabeledError, PipelineData, Signature, Span, SyntaxShape, Type, Value,
 };
 
-use crate::Nuts;
+use crate::{Nuts, commands::SignatureExt};
 
 pub(crate) struct Get;
 
@@ -24,6 +24,7 @@ impl PluginCommand for Get {
             )
             .required("key", SyntaxShape::String, "The key to get the value of")
             .switch("binary", "Return the value in binary format", Some('b'))
+            .connection_flags()
             .input_output_types(vec![(Type::Any, Type::String), (Type::Any, Type::Binary)])
     }
 
@@ -38,41 +39,32 @@ impl PluginCommand for Get {
     fn run(
         &self,
         plugin: &Self::Plugin,
-        _engine: &EngineInterface,
+        engine: &EngineInterface,
         call: &EvaluatedCall,
         _input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         let bucket: String = call.req(0)?;
         let key: String = call.req(1)?;
         let binary_output = call.has_flag("binary")?;
-        let client = plugin.nats.read().unwrap();
-        match client.as_ref() {
-            Some(client) => {
-                let value = plugin.runtime.block_on({
-                    let client = client.clone();
-                    async move {
-                        let value = jetstream::new(client)
-                            .get_key_value(&bucket)
-                            .await
-                            .map_err(|error| LabeledError::new(error.to_string()))?
-                            .get(&key)
-                            .await
-                            .map_err(|error| LabeledError::new(error.to_string()))?
-                            .ok_or_else(|| {
-                                LabeledError::new(format!("Key {key} not found in bucket {bucket}"))
-                            })?;
-                        Ok::<Value, LabeledError>(if binary_output {
-                            value.into_value(Span::unknown())
-                        } else {
-                            String::from_utf8_lossy(&value).into_value(Span::unknown())
-                        })
-                    }
+        let client = plugin.client(engine, call)?;
+        let value = plugin.runtime.block_on(async move {
+            let value = jetstream::new(client.clone())
+                .get_key_value(&bucket)
+                .await
+                .map_err(|error| LabeledError::new(error.to_string()))?
+                .get(&key)
+                .await
+                .map_err(|error| LabeledError::new(error.to_string()))?
+                .ok_or_else(|| {
+                    LabeledError::new(format!("Key {key} not found in bucket {bucket}"))
                 })?;
-                Ok(PipelineData::Value(value, None))
-            }
-            None => Err(LabeledError::new(
-                "Not connected to NATS server. Call `nuts connect` first",
-            )),
-        }
+            client.close().await?;
+            Ok::<Value, LabeledError>(if binary_output {
+                value.into_value(Span::unknown())
+            } else {
+                String::from_utf8_lossy(&value).into_value(Span::unknown())
+            })
+        })?;
+        Ok(PipelineData::Value(value, None))
     }
 }
@@ -6,7 +6,7 @@ use nu_protocol::{
     Example, IntoValue, LabeledError, PipelineData, Signature, Span, SyntaxShape, Type,
 };
 
-use crate::Nuts;
+use crate::{Nuts, commands::SignatureExt};
 
 #[derive(Debug)]
 pub(crate) struct List;
@@ -21,6 +21,7 @@ impl PluginCommand for List {
     fn signature(&self) -> Signature {
         Signature::build(self.name())
             .optional("bucket", SyntaxShape::String, "Bucket to list keys for")
+            .connection_flags()
             .input_output_type(Type::Any, Type::List(Type::String.into()))
     }
 
@@ -50,42 +51,37 @@ impl PluginCommand for List {
     fn run(
         &self,
         plugin: &Self::Plugin,
-        _engine: &EngineInterface,
+        engine: &EngineInterface,
         call: &EvaluatedCall,
         _input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         let bucket: Option<String> = call.opt(0)?;
-        let client = plugin.nats.read().unwrap();
-        match client.as_ref() {
-            Some(client) => {
-                let jetstream = jetstream::new(client.clone());
-                let keys = plugin.runtime.block_on(async move {
-                    match bucket {
-                        Some(bucket) => jetstream
-                            .get_key_value(bucket)
-                            .await
-                            .map_err(|error| LabeledError::new(error.to_string()))?
-                            .keys()
-                            .await
-                            .map_err(|error| LabeledError::new(error.to_string()))?
-                            .try_collect::<Vec<String>>()
-                            .await
-                            .map_err(|error| LabeledError::new(error.to_string())),
-                        None => jetstream
-                            .stream_names()
-                            .try_filter_map(|name| async move {
-                                Ok(name.strip_prefix("KV_").map(String::from))
-                            })
-                            .try_collect::<Vec<String>>()
-                            .await
-                            .map_err(|error| LabeledError::new(error.to_string())),
-                    }
-                })?;
-                Ok(PipelineData::Value(keys.into_value(call.head), None))
-            }
-            None => Err(LabeledError::new(
-                "Not connected to NATS server. Call `nuts connect` first",
-            )),
-        }
+        let client = plugin.client(engine, call)?;
+        let jetstream = jetstream::new(client.clone());
+        let keys = plugin.runtime.block_on(async move {
+            let keys = match bucket {
+                Some(bucket) => jetstream
+                    .get_key_value(bucket)
+                    .await
+                    .map_err(|error| LabeledError::new(error.to_string()))?
+                    .keys()
+                    .await
+                    .map_err(|error| LabeledError::new(error.to_string()))?
+                    .try_collect::<Vec<String>>()
+                    .await
+                    .map_err(|error| LabeledError::new(error.to_string())),
+                None => jetstream
+                    .stream_names()
+                    .try_filter_map(
+                        |name| async move { Ok(name.strip_prefix("KV_").map(String::from)) },
+                    )
+                    .try_collect::<Vec<String>>()
+                    .await
+                    .map_err(|error| LabeledError::new(error.to_string())),
+            }?;
+            client.close().await?;
+            Ok::<Vec<String>, LabeledError>(keys)
+        })?;
+        Ok(PipelineData::Value(keys.into_value(call.head), None))
     }
 }
@@ -5,7 +5,7 @@ use nu_protocol::{
     Example, LabeledError, PipelineData, Record, Signature, SyntaxShape, Type, Value,
 };
 
-use crate::Nuts;
+use crate::{Nuts, commands::SignatureExt};
 
 pub(crate) struct Put;
 
@@ -19,6 +19,7 @@ impl PluginCommand for Put {
     fn signature(&self) -> Signature {
         Signature::build(self.name())
             .required("bucket", SyntaxShape::String, "Bucket to put to")
+            .connection_flags()
             .input_output_type(Type::record(), Type::Nothing)
     }
 
@@ -41,32 +42,26 @@ impl PluginCommand for Put {
     fn run(
         &self,
         plugin: &Self::Plugin,
-        _engine: &EngineInterface,
+        engine: &EngineInterface,
         call: &EvaluatedCall,
         input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         let bucket: String = call.req(0)?;
-        match plugin.nats.read().unwrap().as_ref() {
-            Some(client) => {
-                let jetstream = jetstream::new(client.clone());
-                plugin.runtime.block_on(async move {
-                    let store = jetstream
-                        .get_key_value(bucket)
-                        .await
-                        .map_err(|error| LabeledError::new(error.to_string()))?;
-                    if let PipelineData::Value(value, _) = input {
-                        Self::put_value(&store, value)
-                            .await
-                            .map_err(|error| LabeledError::new(error.to_string()))?;
-                    }
-                    Ok::<(), LabeledError>(())
-                })?;
-                Ok(PipelineData::Empty)
+        let client = plugin.client(engine, call)?;
+        let jetstream = jetstream::new(client.clone());
+        plugin.runtime.block_on(async move {
+            let store = jetstream
+                .get_key_value(bucket)
+                .await
+                .map_err(|error| LabeledError::new(error.to_string()))?;
+            if let PipelineData::Value(value, _) = input {
+                Self::put_value(&store, value)
+                    .await
+                    .map_err(|error| LabeledError::new(error.to_string()))?;
             }
-            None => Err(LabeledError::new(
-                "Not connected to NATS server. Call `nuts connect` first",
-            )),
-        }
+            client.close().await
+        })?;
+        Ok(PipelineData::Empty)
     }
 }
 
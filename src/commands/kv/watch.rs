@@ -1,6 +1,6 @@
 use async_nats::jetstream;
 use futures::StreamExt;
-use log::info;
+use log::{info, warn};
 use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
 use nu_protocol::{
     Example, IntoValue, LabeledError, ListStream, PipelineData, Record, ShellError, Signals,
@@ -9,7 +9,7 @@ use nu_protocol::{
 use tokio::{select, sync::mpsc};
 use tokio_util::sync::CancellationToken;
 
-use crate::Nuts;
+use crate::{Nuts, commands::SignatureExt};
 
 pub(crate) struct Watch;
 
@@ -24,6 +24,7 @@ impl PluginCommand for Watch {
         Signature::build(self.name())
             .required("bucket", SyntaxShape::String, "Bucket to watch")
             .optional("key", SyntaxShape::String, "The key to watch")
+            .connection_flags()
             .input_output_type(Type::Any, Type::List(Type::String.into()))
     }
 
@@ -74,78 +75,69 @@ impl PluginCommand for Watch {
     ) -> Result<PipelineData, LabeledError> {
         let bucket: String = call.req(0)?;
         let key: Option<String> = call.opt(1)?;
-        let client = plugin.nats.read().unwrap();
-        match client.as_ref() {
-            Some(client) => {
-                let (tx, mut rx) = mpsc::unbounded_channel();
-                plugin.runtime.spawn({
-                    let client = client.clone();
-                    let engine = engine.clone();
-                    async move {
-                        let jetstream = jetstream::new(client);
-                        let key_value = jetstream
-                            .get_key_value(bucket)
-                            .await
-                            .unwrap();
-                        let mut watch = match key {
-                            Some(key) => key_value
-                                .watch(key)
-                                .await
-                                .unwrap(),
-                            None => key_value
-                                .watch_all()
-                                .await
-                            .unwrap(),
-                        };
+        let client = plugin.client(engine, call)?;
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        plugin.runtime.spawn({
+            let engine = engine.clone();
+            async move {
+                let jetstream = jetstream::new(client.clone());
+                let key_value = jetstream.get_key_value(bucket).await.unwrap();
+                let mut watch = match key {
+                    Some(key) => key_value.watch(key).await.unwrap(),
+                    None => key_value.watch_all().await.unwrap(),
+                };
 
-                        let cancellation = CancellationToken::new();
-                        let _signal_guard = engine.register_signal_handler(Box::new({
-                            let cancellation = cancellation.clone();
-                            move |_| {
-                                info!("Cancel");
-                                cancellation.cancel();
-                            }
-                        })).expect("Failed to register signal handler");
+                let cancellation = CancellationToken::new();
+                let _signal_guard = engine
+                    .register_signal_handler(Box::new({
+                        let cancellation = cancellation.clone();
+                        move |_| {
+                            info!("Cancel");
+                            cancellation.cancel();
+                        }
+                    }))
+                    .expect("Failed to register signal handler");
 
-                        loop {
-                            select! {
-                                _ = cancellation.cancelled() => {
-                                    break;
-                                }
-                                Some(entry) = watch.next() => {
-                                    tx.send(entry).expect("Failed to send key value entry through channel");
-                                }
+                loop {
+                    select! {
+                        _ = cancellation.cancelled() => {
+                            break;
+                        }
+                        _ = tx.closed() => {
+                            break;
+                        }
+                        Some(entry) = watch.next() => {
+                            if tx.send(entry).is_err() {
+                                break;
                             }
                         }
                     }
-                });
+                }
+                if let Err(error) = client.close().await {
+                    warn!("{}", error.msg);
+                }
+            }
+        });
 
-                let handle = plugin.runtime.handle().clone();
-                let stream_iter = std::iter::repeat_with(move || handle.block_on(rx.recv()))
-                    .map_while(move |message| {
-                        message.map(|message| match message {
-                            Ok(entry) => Record::from_iter([(
-                                entry.key,
-                                String::from_utf8_lossy(&entry.value).into_value(Span::unknown()),
-                            )])
-                            .into_value(Span::unknown()),
-                            Err(error) => IntoValue::into_value(
-                                ShellError::LabeledError(
-                                    LabeledError::new(error.to_string()).into(),
-                                ),
-                                Span::unknown(),
-                            ),
-                        })
-                    });
+        let handle = plugin.runtime.handle().clone();
+        let stream_iter =
+            std::iter::repeat_with(move || handle.block_on(rx.recv())).map_while(move |message| {
+                message.map(|message| match message {
+                    Ok(entry) => Record::from_iter([(
+                        entry.key,
+                        String::from_utf8_lossy(&entry.value).into_value(Span::unknown()),
+                    )])
+                    .into_value(Span::unknown()),
+                    Err(error) => IntoValue::into_value(
+                        ShellError::LabeledError(LabeledError::new(error.to_string()).into()),
+                        Span::unknown(),
+                    ),
+                })
+            });
 
-                Ok(PipelineData::ListStream(
-                    ListStream::new(stream_iter, call.head, Signals::empty()),
-                    None,
-                ))
-            }
-            None => Err(LabeledError::new(
-                "Not connected to NATS server. Call `nuts connect` first",
-            )),
-        }
+        Ok(PipelineData::ListStream(
+            ListStream::new(stream_iter, call.head, Signals::empty()),
+            None,
+        ))
     }
 }
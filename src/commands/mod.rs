@@ -1,7 +1,139 @@
+pub(crate) mod bench;
 pub(crate) mod connect;
+pub(crate) mod connections;
+pub(crate) mod context;
+pub(crate) mod disconnect;
+pub(crate) mod events;
+pub(crate) mod js;
 pub(crate) mod kv;
 pub(crate) mod publish;
+pub(crate) mod request;
+pub(crate) mod rtt;
+pub(crate) mod status;
 pub(crate) mod subscribe;
 
+use std::time::Duration;
+
+use nu_plugin::EvaluatedCall;
+use nu_protocol::{LabeledError, Signature, Spanned, SyntaxShape};
+
 pub(crate) use publish::Publish;
+pub(crate) use request::Request;
 pub(crate) use subscribe::Subscribe;
+
+/// Names of the flags added by [`SignatureExt::auth_flags`]
+pub(crate) const AUTH_FLAGS: &[&str] =
+    &["user", "password", "creds", "nkey", "token", "jwt", "seed"];
+
+pub(crate) trait SignatureExt {
+    /// Adds the flags that select which registered connection the command uses,
+    /// or the server to open a temporary connection to
+    fn connection_flags(self) -> Self;
+
+    /// Adds the flags to authenticate with
+    fn auth_flags(self) -> Self;
+}
+
+/// Shape of an argument taking a string or a list of strings.
+/// A list literal would be parsed as a string if the string shape came first.
+pub(crate) fn string_or_list_shape() -> SyntaxShape {
+    SyntaxShape::OneOf(vec![
+        SyntaxShape::List(Box::new(SyntaxShape::String)),
+        SyntaxShape::String,
+    ])
+}
+
+impl SignatureExt for Signature {
+    fn connection_flags(self) -> Self {
+        self.named(
+            "connection",
+            SyntaxShape::String,
+            "Name of the connection to use instead of the default one",
+            None,
+        )
+        .named(
+            "server",
+            string_or_list_shape(),
+            "Server URL to open a temporary connection to, which is closed after the command",
+            None,
+        )
+        .auth_flags()
+    }
+
+    fn auth_flags(self) -> Self {
+        self.named(
+            "user",
+            SyntaxShape::String,
+            "Username to authenticate with",
+            None,
+        )
+        .named(
+            "password",
+            SyntaxShape::String,
+            "Password to authenticate with",
+            None,
+        )
+        .named(
+            "creds",
+            SyntaxShape::String,
+            "Contents of a credentials file to authenticate with",
+            None,
+        )
+        .named(
+            "nkey",
+            SyntaxShape::String,
+            "NKEY to authenticate with",
+            None,
+        )
+        .named(
+            "token",
+            SyntaxShape::String,
+            "Token to authenticate with",
+            None,
+        )
+        .named(
+            "jwt",
+            SyntaxShape::String,
+            "User JWT to authenticate with. Requires `--seed`",
+            None,
+        )
+        .named(
+            "seed",
+            SyntaxShape::String,
+            "NKEY seed to sign the server nonce with when authenticating with `--jwt`",
+            None,
+        )
+    }
+}
+
+/// Returns the rate of `count` operations in `elapsed`, or 0 if no time elapsed
+pub(crate) fn per_second(count: usize, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        0.0
+    } else {
+        count as f64 / elapsed.as_secs_f64()
+    }
+}
+
+/// Reads an int flag like `--count` that has to be positive
+pub(crate) fn get_count_flag<T>(call: &EvaluatedCall, name: &str) -> Result<Option<T>, LabeledError>
+where
+    T: TryFrom<i64> + Default + PartialOrd,
+{
+    let count: Option<Spanned<i64>> = call.get_flag(name)?;
+    count.map(|count| count_from_flag(name, count)).transpose()
+}
+
+/// Converts the value of the flag `name` into a positive count
+pub(crate) fn count_from_flag<T>(name: &str, count: Spanned<i64>) -> Result<T, LabeledError>
+where
+    T: TryFrom<i64> + Default + PartialOrd,
+{
+    T::try_from(count.item)
+        .ok()
+        .filter(|count| *count > T::default())
+        .ok_or_else(|| {
+            LabeledError::new(format!("Invalid `--{name}`"))
+                .with_label("must be positive", count.span)
+        })
+}
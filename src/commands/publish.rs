@@ -1,21 +1,131 @@
 use anyhow::Context;
-use async_nats::{
-    Client, HeaderMap, HeaderName, HeaderValue,
-    header::{IntoHeaderName, IntoHeaderValue},
+use async_nats::{Client, HeaderMap};
+use std::{
+    io::Read,
+    iter,
+    time::{Duration, Instant},
 };
-use futures::future;
+
+use futures::{StreamExt, TryStreamExt, future, stream};
 use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
 use nu_protocol::{
-    Example, LabeledError, PipelineData, Record, ShellError, Signature, Span, SyntaxShape, Type,
-    Value,
+    ByteStream, Example, Filesize, IntoValue, LabeledError, PipelineData, Record, Signals,
+    Signature, Span, Spanned, SyntaxShape, Type, Value,
+};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Nuts,
+    commands::{SignatureExt, count_from_flag, per_second, string_or_list_shape},
+    format::PayloadFormat,
+    message::{OutgoingMessage, headers_from_flag},
+    rate_limiter::{Rate, RateLimiter},
 };
-use nu_utils::SharedCow;
 
-use crate::Nuts;
+/// Number of messages published at the same time unless `--concurrency` is given.
+/// `nuts js pub` and `nuts req` publish the rows of a list with the same concurrency.
+pub(crate) const DEFAULT_CONCURRENCY: usize = 64;
 
 #[derive(Debug)]
 pub(crate) struct Publish;
 
+/// How byte stream input is split into messages
+#[derive(Debug)]
+enum Framing {
+    Lines,
+    Delimiter(Vec<u8>),
+    ChunkSize(usize),
+}
+
+impl Framing {
+    fn from_call(call: &EvaluatedCall) -> Result<Option<Self>, LabeledError> {
+        let split: Option<Spanned<String>> = call.get_flag("split")?;
+        let chunk_size: Option<Spanned<Filesize>> = call.get_flag("chunk-size")?;
+        match (split, chunk_size) {
+            (Some(_), Some(chunk_size)) => Err(LabeledError::new("Conflicting flags")
+                .with_label("cannot be used together with `--split`", chunk_size.span)),
+            (Some(split), None) if split.item == "lines" => Ok(Some(Self::Lines)),
+            // An empty delimiter would split the input into endless empty frames
+            (Some(delimiter), None) if delimiter.item.is_empty() => {
+                Err(LabeledError::new("Invalid delimiter")
+                    .with_label("must not be empty", delimiter.span))
+            }
+            (Some(delimiter), None) => Ok(Some(Self::Delimiter(delimiter.item.into_bytes()))),
+            (None, Some(Spanned { item, span })) => count_from_flag(
+                "chunk-size",
+                Spanned {
+                    item: item.get(),
+                    span,
+                },
+            )
+            .map(|chunk_size| Some(Self::ChunkSize(chunk_size))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    /// Splits the stream into frames while it is read
+    fn frames(
+        self,
+        byte_stream: ByteStream,
+    ) -> Box<dyn Iterator<Item = Result<Value, LabeledError>> + Send> {
+        let span = byte_stream.span();
+        match self {
+            Self::Lines => match byte_stream.lines() {
+                Some(lines) => Box::new(lines.map(move |line| {
+                    line.map(|line| Value::string(line, span))
+                        .map_err(LabeledError::from)
+                })),
+                None => Box::new(iter::empty()),
+            },
+            Self::Delimiter(delimiter) => match byte_stream.split(delimiter) {
+                Some(frames) => {
+                    let mut frames = frames.peekable();
+                    Box::new(iter::from_fn(move || {
+                        let frame = frames.next()?;
+                        // Input ending with the delimiter does not end with an empty message
+                        if matches!(&frame, Ok(frame) if frame.is_empty())
+                            && frames.peek().is_none()
+                        {
+                            return None;
+                        }
+                        Some(
+                            frame
+                                .map(|frame| Value::binary(frame, span))
+                                .map_err(LabeledError::from),
+                        )
+                    }))
+                }
+                None => Box::new(iter::empty()),
+            },
+            Self::ChunkSize(chunk_size) => {
+                let mut reader = byte_stream.reader();
+                Box::new(iter::from_fn(move || {
+                    let mut chunk = Vec::with_capacity(chunk_size);
+                    match reader
+                        .as_mut()?
+                        .take(chunk_size as u64)
+                        .read_to_end(&mut chunk)
+                    {
+                        Ok(0) => None,
+                        Ok(_) => Some(Ok(Value::binary(chunk, span))),
+                        Err(error) => Some(Err(LabeledError::new("Failed to read input")
+                            .with_label(error.to_string(), span))),
+                    }
+                }))
+            }
+        }
+    }
+}
+
+/// Flags of `nuts pub` applied to every message
+#[derive(Debug, Default)]
+pub(crate) struct PublishSettings {
+    pub(crate) subject: Option<String>,
+    pub(crate) reply: Option<String>,
+    pub(crate) format: Option<PayloadFormat>,
+    pub(crate) headers: HeaderMap,
+}
+
 impl PluginCommand for Publish {
     type Plugin = Nuts;
 
@@ -24,38 +134,81 @@ impl PluginCommand for Publish {
     }
 
     fn signature(&self) -> Signature {
-        let record_with_string_payload_type = Type::Record(
-            [
-                ("headers".to_owned(), Type::record()),
-                ("payload".to_owned(), Type::String),
-            ]
-            .into(),
-        );
-        let record_with_binary_payload_type = Type::Record(
-            [
-                ("headers".to_owned(), Type::record()),
-                ("payload".to_owned(), Type::Binary),
-            ]
-            .into(),
-        );
         Signature::build(self.name())
-            .required("subject", SyntaxShape::String, "Subject to publish to")
-            .input_output_types(vec![
-                (Type::String, Type::Nothing),
-                (Type::Binary, Type::Nothing),
-                (record_with_string_payload_type.clone(), Type::Nothing),
-                (record_with_binary_payload_type.clone(), Type::Nothing),
-                (Type::List(Type::String.into()), Type::Nothing),
-                (Type::List(Type::Binary.into()), Type::Nothing),
-                (
-                    Type::List(record_with_string_payload_type.into()),
-                    Type::Nothing,
-                ),
-                (
-                    Type::List(record_with_binary_payload_type.into()),
-                    Type::Nothing,
-                ),
-            ])
+            .optional(
+                "subject",
+                SyntaxShape::String,
+                "Subject to publish to. Can be omitted if every input record has a subject field",
+            )
+            .named(
+                "reply",
+                SyntaxShape::String,
+                "Subject the receivers should reply to",
+                Some('r'),
+            )
+            .named(
+                "concurrency",
+                SyntaxShape::Int,
+                "Maximum number of messages published at the same time. Defaults to 64",
+                None,
+            )
+            .switch(
+                "ordered",
+                "Publish the messages in the order of the input, one at a time",
+                None,
+            )
+            .named(
+                "rate",
+                SyntaxShape::OneOf(vec![SyntaxShape::Int, SyntaxShape::String]),
+                "Maximum number of messages per second, or per duration like 100/10ms",
+                None,
+            )
+            .named(
+                "burst",
+                SyntaxShape::Int,
+                "Number of messages that can be published at once after a pause. Requires `--rate`. Defaults to 1",
+                None,
+            )
+            .named(
+                "header",
+                string_or_list_shape(),
+                "Header in the form of name=value, or a list of them, added to every message that does not set it",
+                Some('H'),
+            )
+            .named(
+                "split",
+                SyntaxShape::String,
+                "Publish every line of byte stream input as a message with `lines`, or split it at the given delimiter",
+                Some('s'),
+            )
+            .named(
+                "chunk-size",
+                SyntaxShape::Filesize,
+                "Publish byte stream input in chunks of this size",
+                None,
+            )
+            .named(
+                "format",
+                SyntaxShape::String,
+                "Serialize the input value, or every value of a list, as json, msgpack or nuon and set the Content-Type header",
+                Some('f'),
+            )
+            .switch(
+                "summary",
+                "Return a summary of the published messages instead of stopping at the first failure",
+                None,
+            )
+            .connection_flags()
+            .input_output_types(
+                [
+                    Self::input_output_types(Type::Nothing, Type::Nothing),
+                    // Any value can be serialized with `--format`
+                    vec![(Type::Any, Type::Nothing)],
+                    Self::input_output_types(Type::record(), Type::record()),
+                    vec![(Type::Any, Type::record())],
+                ]
+                .concat(),
+            )
     }
 
     fn description(&self) -> &str {
@@ -88,106 +241,332 @@ impl PluginCommand for Publish {
                 description: "Publish multiple messages with headers",
                 result: None,
             },
+            Example {
+                example: "{headers: {Accept: [text/plain application/json]}, payload: 'my message'} | nuts pub subject",
+                description: "Publish a message with a header set multiple times",
+                result: None,
+            },
+            Example {
+                example: "['my message', 'my other message'] | nuts pub subject --header [Content-Type=text/plain Origin=cli]",
+                description: "Add the same headers to every message",
+                result: None,
+            },
+            Example {
+                example: "'my request' | nuts pub service.requests --reply service.replies",
+                description: "Publish a message with a reply subject",
+                result: None,
+            },
+            Example {
+                example: "[{subject: 'orders.new', payload: 'order 1'}, {subject: 'orders.cancelled', payload: 'order 2', reply: 'orders.ack'}] | nuts pub",
+                description: "Publish messages to the subjects of their rows",
+                result: None,
+            },
+            Example {
+                example: "open events.jsonl | lines | nuts pub events --ordered",
+                description: "Publish the lines of a file in order",
+                result: None,
+            },
+            Example {
+                example: "open events.jsonl | lines | nuts pub events --summary",
+                description: "Publish the lines of a file and report the throughput and the failed rows",
+                result: None,
+            },
+            Example {
+                example: "open events.jsonl | lines | nuts pub events --rate 100/sec --burst 10",
+                description: "Replay the lines of a file at 100 messages per second",
+                result: None,
+            },
+            Example {
+                example: "open --raw big.log | nuts pub logs --split lines",
+                description: "Publish every line of a file while it is read",
+                result: None,
+            },
+            Example {
+                example: "open --raw image.bin | nuts pub uploads --chunk-size 64kb",
+                description: "Publish a file in chunks of 64kB",
+                result: None,
+            },
+            Example {
+                example: "[{id: 1, items: [apple pear]}, {id: 2, items: []}] | nuts pub orders --format json",
+                description: "Publish every row of a table as a JSON message",
+                result: None,
+            },
         ]
     }
 
     fn run(
         &self,
         plugin: &Self::Plugin,
-        _engine: &EngineInterface,
+        engine: &EngineInterface,
         call: &EvaluatedCall,
         input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
-        let subject: String = call.req(0)?;
-        let client = plugin.nats.read().unwrap();
-        match client.as_ref() {
-            Some(client) => {
-                plugin.runtime.block_on(async move {
-                    match input {
-                        PipelineData::Value(Value::List { vals, .. }, ..) => {
-                            future::try_join_all(vals.into_iter().map(|value| async {
-                                Self::publish_value(client, &subject, value).await
-                            }))
-                            .await?;
-                        }
-                        PipelineData::Value(value, ..) => {
-                            Self::publish_value(client, &subject, value).await?
-                        }
-                        PipelineData::ListStream(list_stream, ..) => {
-                            future::try_join_all(list_stream.into_iter().map(|value| async {
-                                Self::publish_value(client, &subject, value).await
-                            }))
-                            .await?;
+        let settings = PublishSettings {
+            subject: call.opt(0)?,
+            reply: call.get_flag("reply")?,
+            format: call
+                .get_flag("format")?
+                .map(PayloadFormat::from_flag)
+                .transpose()?,
+            headers: call
+                .get_flag_value("header")
+                .map(headers_from_flag)
+                .transpose()?
+                .unwrap_or_default(),
+        };
+        let concurrency: Option<Spanned<i64>> = call.get_flag("concurrency")?;
+        let concurrency = match concurrency {
+            Some(Spanned { span, .. }) if call.has_flag("ordered")? => {
+                return Err(LabeledError::new("Conflicting flags")
+                    .with_label("cannot be used together with `--ordered`", span));
+            }
+            Some(concurrency) => count_from_flag("concurrency", concurrency)?,
+            // Publishing one message at a time keeps the order of the input
+            None if call.has_flag("ordered")? => 1,
+            None => DEFAULT_CONCURRENCY,
+        };
+        let burst: Option<Spanned<i64>> = call.get_flag("burst")?;
+        let rate_limiter = match (call.get_flag_value("rate"), burst) {
+            (None, Some(burst)) => {
+                return Err(LabeledError::new("Missing `--rate`")
+                    .with_label("can only be used together with `--rate`", burst.span));
+            }
+            (Some(rate), burst) => {
+                let burst = burst
+                    .map(|burst| count_from_flag("burst", burst))
+                    .transpose()?
+                    .unwrap_or(1);
+                Some(RateLimiter::new(Rate::from_value(rate)?, burst))
+            }
+            (None, None) => None,
+        };
+        let framing = Framing::from_call(call)?;
+        let values: Box<dyn Iterator<Item = Result<Value, LabeledError>> + Send> =
+            match (input, framing) {
+                (PipelineData::Value(Value::List { vals, .. }, ..), _) => {
+                    Box::new(vals.into_iter().map(Ok))
+                }
+                (PipelineData::ListStream(list_stream, ..), _) => {
+                    Box::new(list_stream.into_iter().map(Ok))
+                }
+                (PipelineData::ByteStream(byte_stream, ..), Some(framing)) => {
+                    framing.frames(byte_stream)
+                }
+                // Framing flags split strings and binaries as well
+                (
+                    PipelineData::Value(value @ (Value::String { .. } | Value::Binary { .. }), ..),
+                    Some(framing),
+                ) => {
+                    let span = value.span();
+                    framing.frames(ByteStream::read_binary(
+                        value.coerce_into_binary()?,
+                        span,
+                        Signals::empty(),
+                    ))
+                }
+                (PipelineData::ByteStream(byte_stream, ..), None) => Box::new(iter::once(
+                    byte_stream.into_value().map_err(LabeledError::from),
+                )),
+                (PipelineData::Value(value, ..), _) => Box::new(iter::once(Ok(value))),
+                _ => Box::new(iter::empty()),
+            };
+        let summary = call.has_flag("summary")?;
+        let client = plugin.client(engine, call)?;
+        let cancellation = CancellationToken::new();
+        let _signal_guard = engine.register_signal_handler(Box::new({
+            let cancellation = cancellation.clone();
+            move |_| cancellation.cancel()
+        }))?;
+        let start = Instant::now();
+        let summary = plugin.runtime.block_on(async move {
+            // Values are pulled from the input only when a publish slot is free and the rate
+            // limit allows it. Interrupting stops pulling values, but finishes the pending publishes.
+            let publishes = stream::iter(values.enumerate())
+                .then(|value| {
+                    let rate_limiter = &rate_limiter;
+                    async move {
+                        if let Some(rate_limiter) = rate_limiter {
+                            rate_limiter.acquire().await;
                         }
-                        _ => (),
+                        value
+                    }
+                })
+                .take_until(cancellation.cancelled())
+                .map(|(row, value)| {
+                    let (client, settings) = (&client, &settings);
+                    async move {
+                        let result = match value {
+                            Ok(value) => Self::publish_value(client, settings, value).await,
+                            Err(error) => Err(error),
+                        };
+                        (row, result)
                     }
-                    Ok::<(), LabeledError>(())
-                })?;
-                Ok(PipelineData::Empty)
+                })
+                .buffer_unordered(concurrency);
+            let summary = if summary {
+                let mut summary = Summary::default();
+                publishes
+                    .for_each(|(row, result)| {
+                        summary.add(row, result);
+                        future::ready(())
+                    })
+                    .await;
+                Some(summary)
+            } else {
+                publishes
+                    .map(|(_, result)| result)
+                    .try_for_each(|_| future::ok(()))
+                    .await?;
+                None
+            };
+            client
+                .flush()
+                .await
+                .context("Failed to flush published messages")
+                .map_err(|error| LabeledError::new(error.to_string()))?;
+            client.close().await?;
+            Ok::<Option<Summary>, LabeledError>(summary)
+        })?;
+        Ok(match summary {
+            Some(summary) => {
+                PipelineData::Value(summary.into_value(start.elapsed(), call.head), None)
+            }
+            None => PipelineData::Empty,
+        })
+    }
+}
+
+/// Outcome of publishing with `--summary`
+#[derive(Debug, Default)]
+struct Summary {
+    count: usize,
+    bytes: usize,
+    failed: Vec<(usize, LabeledError)>,
+}
+
+impl Summary {
+    fn add(&mut self, row: usize, result: Result<usize, LabeledError>) {
+        match result {
+            Ok(bytes) => {
+                self.count += 1;
+                self.bytes += bytes;
             }
-            None => Err(LabeledError::new(
-                "Not connected to NATS server. Call `nuts connect` first",
-            )),
+            Err(error) => self.failed.push((row, error)),
         }
     }
+
+    fn into_value(mut self, elapsed: Duration, span: Span) -> Value {
+        // Rows finish out of order when published concurrently
+        self.failed.sort_by_key(|(row, _)| *row);
+        let failed = self
+            .failed
+            .into_iter()
+            .map(|(row, error)| {
+                let error = match error.labels.first() {
+                    Some(label) => format!("{}: {}", error.msg, label.text),
+                    None => error.msg,
+                };
+                Record::from_iter([
+                    ("row".to_owned(), Value::int(row as i64, span)),
+                    ("error".to_owned(), error.into_value(span)),
+                ])
+                .into_value(span)
+            })
+            .collect::<Vec<Value>>();
+        Record::from_iter([
+            ("count".to_owned(), Value::int(self.count as i64, span)),
+            ("bytes".to_owned(), Value::filesize(self.bytes as i64, span)),
+            (
+                "elapsed".to_owned(),
+                Value::duration(elapsed.as_nanos() as i64, span),
+            ),
+            (
+                "msgs_per_sec".to_owned(),
+                Value::float(per_second(self.count, elapsed), span),
+            ),
+            ("failed".to_owned(), failed.into_value(span)),
+        ])
+        .into_value(span)
+    }
 }
 
 impl Publish {
-    async fn publish_record(
-        client: &Client,
-        subject: &str,
-        record: SharedCow<Record>,
-        internal_span: Span,
-    ) -> Result<(), LabeledError> {
-        let headers = if let Some(Value::Record { val: headers, .. }) = record.get("headers") {
-            let headers = headers
-                .iter()
-                .map(|(key, value)| {
-                    Ok((
-                        key.clone().into_header_name(),
-                        value.coerce_str().map(|value| value.into_header_value())?,
-                    ))
-                })
-                .collect::<Result<Vec<(HeaderName, HeaderValue)>, ShellError>>()?;
-            HeaderMap::from_iter(headers.into_iter())
-        } else {
-            HeaderMap::new()
-        };
-        let payload = record
-            .get("payload")
-            .cloned()
-            .ok_or_else(|| {
-                LabeledError::new("missing payload")
-                    .with_label("input record must contain a payload field", internal_span)
-            })?
-            .coerce_into_binary()?;
-
-        client
-            .publish_with_headers(subject.to_owned(), headers, payload.into())
-            .await
-            .context("Failed to publish to NATS subject")
-            .map_err(|error| LabeledError::new(error.to_string()))?;
-        Ok(())
+    /// Input types of every kind of message with the output type of single messages and lists
+    pub(crate) fn input_output_types(output: Type, list_output: Type) -> Vec<(Type, Type)> {
+        let record_with_string_payload_type = Type::Record(
+            [
+                ("headers".to_owned(), Type::record()),
+                ("payload".to_owned(), Type::String),
+            ]
+            .into(),
+        );
+        let record_with_binary_payload_type = Type::Record(
+            [
+                ("headers".to_owned(), Type::record()),
+                ("payload".to_owned(), Type::Binary),
+            ]
+            .into(),
+        );
+        let record_with_string_payload_only_type =
+            Type::Record([("payload".to_owned(), Type::String)].into());
+        let record_with_binary_payload_only_type =
+            Type::Record([("payload".to_owned(), Type::Binary)].into());
+        vec![
+            (Type::String, output.clone()),
+            (Type::Binary, output.clone()),
+            (record_with_string_payload_type.clone(), output.clone()),
+            (record_with_binary_payload_type.clone(), output.clone()),
+            (Type::List(Type::String.into()), list_output.clone()),
+            (Type::List(Type::Binary.into()), list_output.clone()),
+            (
+                Type::List(record_with_string_payload_type.into()),
+                list_output.clone(),
+            ),
+            (
+                Type::List(record_with_binary_payload_type.into()),
+                list_output.clone(),
+            ),
+            (record_with_string_payload_only_type.clone(), output.clone()),
+            (record_with_binary_payload_only_type.clone(), output.clone()),
+            (
+                Type::List(record_with_string_payload_only_type.into()),
+                list_output.clone(),
+            ),
+            (
+                Type::List(record_with_binary_payload_only_type.into()),
+                list_output.clone(),
+            ),
+        ]
     }
 
-    async fn publish_value(
+    /// Publishes a message and returns the size of its payload
+    pub(crate) async fn publish_value(
         client: &Client,
-        subject: &str,
+        settings: &PublishSettings,
         value: Value,
-    ) -> Result<(), LabeledError> {
-        match value {
-            Value::Record {
-                val, internal_span, ..
-            } => Self::publish_record(client, subject, val, internal_span).await?,
-            value => {
-                let payload = value.clone().coerce_into_binary()?;
+    ) -> Result<usize, LabeledError> {
+        let span = value.span();
+        let mut message = match settings.format {
+            Some(format) => OutgoingMessage::encode(&value, format)?,
+            None => OutgoingMessage::from_value(value)?,
+        };
+        message.add_default_headers(&settings.headers);
+        let subject = message.subject_or(settings.subject.as_deref(), span)?;
+        let reply = message.reply.or_else(|| settings.reply.clone());
+        let size = message.payload.len();
+        let payload = message.payload.into();
+        match (message.headers, reply) {
+            (Some(headers), Some(reply)) => {
                 client
-                    .publish(subject.to_owned(), payload.into())
+                    .publish_with_reply_and_headers(subject, reply, headers, payload)
                     .await
-                    .context("Failed to publish to NATS subject")
-                    .map_err(|error| LabeledError::new(error.to_string()))?;
             }
+            (Some(headers), None) => client.publish_with_headers(subject, headers, payload).await,
+            (None, Some(reply)) => client.publish_with_reply(subject, reply, payload).await,
+            (None, None) => client.publish(subject, payload).await,
         }
-        Ok(())
+        .context("Failed to publish to NATS subject")
+        .map_err(|error| LabeledError::new(error.to_string()))?;
+        Ok(size)
     }
 }
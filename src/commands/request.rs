@@ -0,0 +1,350 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use async_nats::{Client, Request as NatsRequest, RequestErrorKind, StatusCode, Subscriber};
+use futures::{StreamExt, TryStreamExt, future, stream};
+use log::{info, warn};
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, IntoValue, LabeledError, ListStream, PipelineData, ShellError, Signals,
+    Signature, Span, Spanned, SyntaxShape, Type, Value,
+};
+use tokio::{select, sync::mpsc, time::Instant};
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    Nuts,
+    commands::{SignatureExt, get_count_flag, publish::DEFAULT_CONCURRENCY},
+    message::{OutgoingMessage, message_to_value},
+};
+
+#[derive(Debug)]
+pub(crate) struct Request;
+
+impl PluginCommand for Request {
+    type Plugin = Nuts;
+
+    fn name(&self) -> &str {
+        "nuts req"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .required(
+                "subject",
+                SyntaxShape::String,
+                "Subject to send the request to, unless the input record has a subject field",
+            )
+            .named(
+                "timeout",
+                SyntaxShape::Duration,
+                "Time to wait for the reply. Defaults to the request timeout of the connection",
+                Some('t'),
+            )
+            .switch(
+                "many",
+                "Stream every reply until the timeout, `--max-replies` or `--stall` is reached",
+                Some('m'),
+            )
+            .named(
+                "max-replies",
+                SyntaxShape::Int,
+                "Stop after this many replies. Requires `--many`",
+                None,
+            )
+            .named(
+                "stall",
+                SyntaxShape::Duration,
+                "Stop when no reply arrives for this long after the first one. Requires `--many`",
+                None,
+            )
+            .switch(
+                "binary",
+                "Do not decode the reply payload as string",
+                Some('b'),
+            )
+            .connection_flags()
+            .input_output_types(vec![
+                (Type::Nothing, Type::record()),
+                (Type::String, Type::record()),
+                (Type::Binary, Type::record()),
+                (Type::record(), Type::record()),
+                (Type::List(Type::Any.into()), Type::table()),
+            ])
+            .category(Category::Generators)
+    }
+
+    fn description(&self) -> &str {
+        "Send a request to a subject and wait for the reply"
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        vec!["nats", "req", "request", "reply"]
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                example: "'my request' | nuts req service.echo",
+                description: "Send a string request",
+                result: None,
+            },
+            Example {
+                example: "{headers: {'Content-Type': 'text/plain'}, payload: 'my request'} | nuts req service.echo --timeout 500ms",
+                description: "Send a request with headers and a custom timeout",
+                result: None,
+            },
+            Example {
+                example: "['first', 'second'] | nuts req service.echo | get payload",
+                description: "Send multiple requests concurrently. Replies are in the order of the requests",
+                result: None,
+            },
+            Example {
+                example: "nuts req service.discover --many --timeout 1sec --stall 100ms",
+                description: "Collect the replies of every service instance",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let subject: Spanned<String> = call.req(0)?;
+        let timeout: Option<Duration> = call.get_flag("timeout")?;
+        let binary_output = call.has_flag("binary")?;
+        let span = call.head;
+        if call.has_flag("many")? {
+            return Self::run_many(plugin, engine, call, subject, timeout, binary_output, input);
+        }
+        if let Some((flag, _)) = call
+            .named
+            .iter()
+            .find(|(flag, _)| ["max-replies", "stall"].contains(&flag.item.as_str()))
+        {
+            return Err(LabeledError::new("Missing `--many`")
+                .with_label("can only be used together with `--many`", flag.span));
+        }
+        let client = plugin.client(engine, call)?;
+        let output = plugin.runtime.block_on(async {
+            let request = |value| async {
+                let reply = Self::request(
+                    &client,
+                    &subject,
+                    timeout,
+                    OutgoingMessage::from_value(value)?,
+                )
+                .await?;
+                Ok::<Value, LabeledError>(message_to_value(reply, binary_output, span))
+            };
+            let output = match input {
+                PipelineData::Value(Value::List { vals, .. }, ..) => stream::iter(vals)
+                    .map(request)
+                    .buffered(DEFAULT_CONCURRENCY)
+                    .try_collect::<Vec<Value>>()
+                    .await?
+                    .into_value(span),
+                PipelineData::ListStream(list_stream, ..) => stream::iter(list_stream)
+                    .map(request)
+                    .buffered(DEFAULT_CONCURRENCY)
+                    .try_collect::<Vec<Value>>()
+                    .await?
+                    .into_value(span),
+                PipelineData::Value(value, ..) => request(value).await?,
+                _ => request(Value::binary(Vec::new(), span)).await?,
+            };
+            client.close().await?;
+            Ok::<Value, LabeledError>(output)
+        })?;
+        Ok(PipelineData::Value(output, None))
+    }
+}
+
+impl Request {
+    /// Publishes a single request with a reply subject of a private inbox and streams every reply
+    /// arriving to the inbox
+    fn run_many(
+        plugin: &Nuts,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        subject: Spanned<String>,
+        timeout: Option<Duration>,
+        binary_output: bool,
+        input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let max_replies: Option<usize> = get_count_flag(call, "max-replies")?;
+        let stall: Option<Duration> = call.get_flag("stall")?;
+        let message = match input {
+            PipelineData::Value(Value::List { .. }, ..) | PipelineData::ListStream(..) => {
+                return Err(LabeledError::new("Invalid input")
+                    .with_label("`--many` sends a single request", call.head));
+            }
+            PipelineData::Value(value, ..) => OutgoingMessage::from_value(value)?,
+            _ => OutgoingMessage::from_value(Value::binary(Vec::new(), call.head))?,
+        };
+        let span = call.head;
+        let request_subject = Self::subject(&message, &subject)?;
+
+        let client = plugin.client(engine, call)?;
+        let timeout = timeout.or(client.timeout());
+        let subscription = plugin.runtime.block_on(async {
+            let inbox = client.new_inbox();
+            let subscription = client
+                .subscribe(inbox.clone())
+                .await
+                .context("Failed to subscribe to inbox")?;
+            let payload = message.payload.into();
+            match message.headers {
+                Some(headers) => {
+                    client
+                        .publish_with_reply_and_headers(request_subject, inbox, headers, payload)
+                        .await
+                }
+                None => {
+                    client
+                        .publish_with_reply(request_subject, inbox, payload)
+                        .await
+                }
+            }
+            .context("Failed to publish request")?;
+            client.flush().await.context("Failed to flush connection")?;
+            Ok::<Subscriber, anyhow::Error>(subscription)
+        });
+        let mut subscription =
+            subscription.map_err(|error| LabeledError::new(format!("{error:#}")))?;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        plugin.runtime.spawn({
+            let engine = engine.clone();
+            async move {
+                let cancellation = CancellationToken::new();
+                let _signal_guard = engine
+                    .register_signal_handler(Box::new({
+                        let cancellation = cancellation.clone();
+                        move |_| {
+                            info!("Cancel");
+                            cancellation.cancel();
+                        }
+                    }))
+                    .expect("Failed to register signal handler");
+
+                let deadline = timeout.map(|timeout| Instant::now() + timeout);
+                let mut stall_deadline = None;
+                let mut replies = 0;
+                loop {
+                    select! {
+                        _ = cancellation.cancelled() => {
+                            break;
+                        }
+                        _ = tx.closed() => {
+                            break;
+                        }
+                        _ = Self::sleep_until(deadline.into_iter().chain(stall_deadline).min()) => {
+                            break;
+                        }
+                        message = subscription.next() => match message {
+                            Some(message) if message.status == Some(StatusCode::NO_RESPONDERS) => {
+                                let _ = tx.send(Err(LabeledError::new("No responders").with_label(
+                                    "nothing is subscribed to reply on this subject",
+                                    subject.span,
+                                )));
+                                break;
+                            }
+                            Some(message) => {
+                                if tx.send(Ok(message)).is_err() {
+                                    break;
+                                }
+                                replies += 1;
+                                if max_replies.is_some_and(|max_replies| replies >= max_replies) {
+                                    break;
+                                }
+                                stall_deadline = stall.map(|stall| Instant::now() + stall);
+                            }
+                            None => break,
+                        }
+                    };
+                }
+                if let Err(error) = subscription.unsubscribe().await {
+                    warn!("Failed to unsubscribe from inbox: {error}");
+                }
+                if let Err(error) = client.close().await {
+                    warn!("{}", error.msg);
+                }
+            }
+        });
+
+        let handle = plugin.runtime.handle().clone();
+        let stream_iter =
+            std::iter::repeat_with(move || handle.block_on(rx.recv())).map_while(move |reply| {
+                reply.map(|reply| match reply {
+                    Ok(message) => message_to_value(message, binary_output, span),
+                    Err(error) => {
+                        IntoValue::into_value(ShellError::LabeledError(error.into()), span)
+                    }
+                })
+            });
+
+        Ok(PipelineData::ListStream(
+            ListStream::new(stream_iter, span, Signals::empty()),
+            None,
+        ))
+    }
+
+    async fn sleep_until(deadline: Option<Instant>) {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => future::pending().await,
+        }
+    }
+
+    pub(crate) async fn request(
+        client: &Client,
+        subject: &Spanned<String>,
+        timeout: Option<Duration>,
+        message: OutgoingMessage,
+    ) -> Result<async_nats::Message, LabeledError> {
+        let request_subject = Self::subject(&message, subject)?;
+        let mut request = NatsRequest::new().payload(message.payload.into());
+        if let Some(headers) = message.headers {
+            request = request.headers(headers);
+        }
+        if timeout.is_some() {
+            request = request.timeout(timeout);
+        }
+        client
+            .send_request(request_subject, request)
+            .await
+            .map_err(|error| Self::request_error(error, subject.span))
+    }
+
+    /// Returns the subject field of the message or the subject argument. Replies always go to a
+    /// private inbox, so the message cannot set a reply subject.
+    fn subject(
+        message: &OutgoingMessage,
+        subject: &Spanned<String>,
+    ) -> Result<String, LabeledError> {
+        if message.reply.is_some() {
+            return Err(LabeledError::new("Invalid request")
+                .with_label("the input record cannot have a `reply` field", subject.span)
+                .with_help("replies are sent to a private inbox, use `nuts pub --reply` instead"));
+        }
+        message.subject_or(Some(&subject.item), subject.span)
+    }
+
+    fn request_error(error: async_nats::RequestError, span: Span) -> LabeledError {
+        match error.kind() {
+            RequestErrorKind::NoResponders => LabeledError::new("No responders")
+                .with_label("nothing is subscribed to reply on this subject", span),
+            RequestErrorKind::TimedOut => LabeledError::new("Request timed out")
+                .with_label("no reply arrived in time on this subject", span)
+                .with_help("increase the time to wait with `--timeout`"),
+            RequestErrorKind::Other => {
+                LabeledError::new("Failed to send request").with_label(error.to_string(), span)
+            }
+        }
+    }
+}
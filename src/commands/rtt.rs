@@ -0,0 +1,218 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use async_nats::Client;
+use futures::StreamExt;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Example, IntoValue, LabeledError, PipelineData, Record, Signature, Span, SyntaxShape, Type,
+    Value,
+};
+
+use crate::{
+    Nuts,
+    commands::{AUTH_FLAGS, SignatureExt, connect::urls_from_value, get_count_flag},
+    connections::{ClientHandle, DEFAULT_CONNECTION_NAME},
+};
+
+#[derive(Debug)]
+pub(crate) struct Rtt;
+
+impl PluginCommand for Rtt {
+    type Plugin = Nuts;
+
+    fn name(&self) -> &str {
+        "nuts rtt"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .named(
+                "count",
+                SyntaxShape::Int,
+                "Number of round trips to measure. Defaults to 5",
+                Some('c'),
+            )
+            .named(
+                "interval",
+                SyntaxShape::Duration,
+                "Time to wait between round trips",
+                Some('i'),
+            )
+            .named(
+                "timeout",
+                SyntaxShape::Duration,
+                "Time to wait for a single round trip. Defaults to 5sec",
+                Some('t'),
+            )
+            .switch(
+                "all",
+                "Measure every registered connection. Cannot be used together with `--connection`, `--server` or the authentication flags",
+                Some('a'),
+            )
+            .connection_flags()
+            .input_output_type(Type::Nothing, Type::table())
+    }
+
+    fn description(&self) -> &str {
+        "Measure the round trip time to the NATS server"
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        vec!["nats", "rtt", "ping", "latency"]
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                example: "nuts rtt",
+                description: "Measure the round trip time of the default connection",
+                result: None,
+            },
+            Example {
+                example: "nuts rtt --all --count 10 --interval 100ms | select connection p99",
+                description: "Compare the round trip time of every connection",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let count: usize = get_count_flag(call, "count")?.unwrap_or(5);
+        let interval: Option<Duration> = call.get_flag("interval")?;
+        let timeout = call
+            .get_flag::<Duration>("timeout")?
+            .unwrap_or(Duration::from_secs(5));
+
+        let targets = if call.has_flag("all")? {
+            // Every registered connection is measured, so selecting one makes no sense
+            if let Some((flag, _)) = call.named.iter().find(|(flag, _)| {
+                ["connection", "server"].contains(&flag.item.as_str())
+                    || AUTH_FLAGS.contains(&flag.item.as_str())
+            }) {
+                return Err(LabeledError::new("Conflicting flags")
+                    .with_label("cannot be used together with `--all`", flag.span));
+            }
+            let connections = plugin
+                .connections
+                .read()
+                .unwrap()
+                .iter()
+                .map(|(name, connection)| (name.clone(), connection.client.clone()))
+                .collect::<Vec<_>>();
+            connections
+                .into_iter()
+                .map(|(name, client)| {
+                    let activity = plugin.lifecycle.activity(engine)?;
+                    Ok((name, ClientHandle::registered(client, activity)))
+                })
+                .collect::<Result<Vec<(String, ClientHandle)>, LabeledError>>()?
+        } else {
+            let client = plugin.client(engine, call)?;
+            let name = match (
+                call.get_flag::<String>("connection")?,
+                call.get_flag_value("server"),
+            ) {
+                (Some(name), _) => name,
+                // Temporary connections are named after the servers they were opened to
+                (None, Some(server)) => urls_from_value(server)?.join(","),
+                (None, None) => plugin
+                    .connections
+                    .read()
+                    .unwrap()
+                    .default_name()
+                    .unwrap_or(DEFAULT_CONNECTION_NAME)
+                    .to_owned(),
+            };
+            vec![(name, client)]
+        };
+
+        let rows = targets
+            .into_iter()
+            .map(|(name, client)| {
+                let mut samples = Vec::with_capacity(count);
+                for index in 0..count {
+                    engine.signals().check(&call.head)?;
+                    if let (Some(interval), true) = (interval, index > 0) {
+                        plugin.runtime.block_on(tokio::time::sleep(interval));
+                    }
+                    samples.push(
+                        plugin
+                            .runtime
+                            .block_on(Self::round_trip(&client, timeout))
+                            .map_err(|error| {
+                                LabeledError::new(format!(
+                                    "Failed to measure round trip time of connection `{name}`"
+                                ))
+                                .with_label(format!("{error:#}"), call.head)
+                            })?,
+                    );
+                }
+                plugin.runtime.block_on(client.close())?;
+                Ok(Self::summary(name, samples, call.head))
+            })
+            .collect::<Result<Vec<Value>, LabeledError>>()?;
+        Ok(PipelineData::Value(rows.into_value(call.head), None))
+    }
+}
+
+impl Rtt {
+    /// Sends a message to a private inbox of the client and waits for the server to deliver it back,
+    /// since flushing alone only waits for the outgoing buffer to be written.
+    /// Requires echo to be enabled on the connection.
+    async fn round_trip(client: &Client, timeout: Duration) -> anyhow::Result<Duration> {
+        let inbox = client.new_inbox();
+        let mut subscription = client
+            .subscribe(inbox.clone())
+            .await
+            .context("Failed to subscribe to inbox")?;
+        // Make sure the subscription is registered on the server before measuring
+        client.flush().await.context("Failed to flush connection")?;
+
+        let start = Instant::now();
+        client
+            .publish(inbox, Default::default())
+            .await
+            .context("Failed to publish to inbox")?;
+        client.flush().await.context("Failed to flush connection")?;
+        tokio::time::timeout(timeout, subscription.next())
+            .await
+            .context(
+                "Timed out waiting for the server. Is the connection started with `--no-echo`?",
+            )?
+            .context("Subscription closed")?;
+        Ok(start.elapsed())
+    }
+
+    fn summary(connection: String, mut samples: Vec<Duration>, span: Span) -> Value {
+        let duration = |duration: Duration| Value::duration(duration.as_nanos() as i64, span);
+        let rtts = samples
+            .iter()
+            .copied()
+            .map(duration)
+            .collect::<Vec<Value>>();
+        samples.sort();
+        let average = samples.iter().sum::<Duration>() / samples.len() as u32;
+        Record::from_iter([
+            ("connection".to_owned(), connection.into_value(span)),
+            ("rtt".to_owned(), rtts.into_value(span)),
+            ("min".to_owned(), duration(samples[0])),
+            ("avg".to_owned(), duration(average)),
+            ("max".to_owned(), duration(samples[samples.len() - 1])),
+            ("p99".to_owned(), duration(percentile(&samples, 99.0))),
+        ])
+        .into_value(span)
+    }
+}
+
+/// Returns the nearest-rank percentile of sorted samples
+pub(crate) fn percentile(sorted_samples: &[Duration], percentile: f64) -> Duration {
+    let rank = (percentile / 100.0 * sorted_samples.len() as f64).ceil() as usize;
+    sorted_samples[rank.clamp(1, sorted_samples.len()) - 1]
+}
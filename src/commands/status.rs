@@ -0,0 +1,134 @@
+use std::sync::atomic::Ordering;
+
+use async_nats::jetstream;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{Example, IntoValue, LabeledError, PipelineData, Record, Signature, Type, Value};
+
+use crate::{Nuts, commands::SignatureExt};
+
+#[derive(Debug)]
+pub(crate) struct Status;
+
+impl PluginCommand for Status {
+    type Plugin = Nuts;
+
+    fn name(&self) -> &str {
+        "nuts status"
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build(self.name())
+            .connection_flags()
+            .input_output_type(Type::Nothing, Type::record())
+    }
+
+    fn description(&self) -> &str {
+        "Show connection state, server info and client statistics"
+    }
+
+    fn extra_description(&self) -> &str {
+        "The `cluster` field is always null, since the cluster name is not part of the server info the client receives."
+    }
+
+    fn search_terms(&self) -> Vec<&str> {
+        vec!["nats", "status", "info", "statistics"]
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                example: "nuts status",
+                description: "Show the status of the default connection",
+                result: None,
+            },
+            Example {
+                example: "nuts status --connection staging",
+                description: "Show the status of a named connection",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let client = plugin.client(engine, call)?;
+        let jetstream_enabled = plugin.runtime.block_on({
+            let client = client.clone();
+            async move { jetstream::new(client).query_account().await.is_ok() }
+        });
+
+        let server_info = client.server_info();
+        let statistics = client.statistics();
+        let span = call.head;
+        let record = Record::from_iter([
+            (
+                "state".to_owned(),
+                client.connection_state().to_string().into_value(span),
+            ),
+            (
+                "server_id".to_owned(),
+                server_info.server_id.into_value(span),
+            ),
+            (
+                "server_name".to_owned(),
+                server_info.server_name.into_value(span),
+            ),
+            ("version".to_owned(), server_info.version.into_value(span)),
+            // The client does not receive the cluster name, but scripts can rely on the field
+            ("cluster".to_owned(), Value::nothing(span)),
+            ("host".to_owned(), server_info.host.into_value(span)),
+            ("port".to_owned(), server_info.port.into_value(span)),
+            ("jetstream".to_owned(), jetstream_enabled.into_value(span)),
+            (
+                "max_payload".to_owned(),
+                Value::filesize(server_info.max_payload as i64, span),
+            ),
+            (
+                "tls_required".to_owned(),
+                server_info.tls_required.into_value(span),
+            ),
+            (
+                "lame_duck_mode".to_owned(),
+                server_info.lame_duck_mode.into_value(span),
+            ),
+            (
+                "connect_urls".to_owned(),
+                server_info.connect_urls.into_value(span),
+            ),
+            (
+                "in_msgs".to_owned(),
+                Value::int(statistics.in_messages.load(Ordering::Relaxed) as i64, span),
+            ),
+            (
+                "out_msgs".to_owned(),
+                Value::int(statistics.out_messages.load(Ordering::Relaxed) as i64, span),
+            ),
+            (
+                "in_bytes".to_owned(),
+                Value::filesize(statistics.in_bytes.load(Ordering::Relaxed) as i64, span),
+            ),
+            (
+                "out_bytes".to_owned(),
+                Value::filesize(statistics.out_bytes.load(Ordering::Relaxed) as i64, span),
+            ),
+            (
+                // The initial connection is counted as well
+                "reconnects".to_owned(),
+                Value::int(
+                    statistics
+                        .connects
+                        .load(Ordering::Relaxed)
+                        .saturating_sub(1) as i64,
+                    span,
+                ),
+            ),
+        ]);
+        plugin.runtime.block_on(client.close())?;
+        Ok(PipelineData::Value(record.into_value(span), None))
+    }
+}
@@ -1,5 +1,5 @@
 use futures::StreamExt;
-use log::info;
+use log::{info, warn};
 use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
 use nu_protocol::{
     Category, Example, IntoValue, LabeledError, ListStream, PipelineData, Signals, Signature, Span,
@@ -8,7 +8,7 @@ use nu_protocol::{
 use tokio::{select, sync::mpsc};
 use tokio_util::sync::CancellationToken;
 
-use crate::Nuts;
+use crate::{Nuts, commands::SignatureExt};
 
 pub(crate) struct Subscribe;
 
@@ -23,6 +23,7 @@ impl PluginCommand for Subscribe {
         Signature::build(self.name())
             .required("subject", SyntaxShape::String, "Subject to consume from")
             .switch("binary", "Do not decode binary as string", Some('b'))
+            .connection_flags()
             .input_output_type(Type::Any, Type::String)
             .input_output_type(Type::Any, Type::Binary)
             .category(Category::Generators)
@@ -53,63 +54,65 @@ impl PluginCommand for Subscribe {
     ) -> Result<PipelineData, LabeledError> {
         let subject: String = call.req(0)?;
         let binary_output = call.has_flag("binary")?;
-        let client = plugin.nats.read().unwrap();
-        match client.as_ref() {
-            Some(client) => {
-                let (tx, mut rx) = mpsc::unbounded_channel();
-                plugin.runtime.spawn({
-                    let client = client.clone();
-                    let engine = engine.clone();
-                    async move {
-                        info!("Spawned subscription");
-                        let mut subscription = client
-                            .subscribe(subject.clone())
-                            .await
-                            .unwrap_or_else(|_| panic!("Failed to subscribe to subject {}", subject));
+        let client = plugin.client(engine, call)?;
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        plugin.runtime.spawn({
+            let engine = engine.clone();
+            async move {
+                info!("Spawned subscription");
+                let mut subscription = client
+                    .subscribe(subject.clone())
+                    .await
+                    .unwrap_or_else(|_| panic!("Failed to subscribe to subject {}", subject));
 
-                        info!("Subscribed");
-                        let cancellation = CancellationToken::new();
-                        let _signal_guard = engine.register_signal_handler(Box::new({
-                            let cancellation = cancellation.clone();
-                            move |_| {
-                                info!("Cancel");
-                                cancellation.cancel();
+                info!("Subscribed");
+                let cancellation = CancellationToken::new();
+                let _signal_guard = engine
+                    .register_signal_handler(Box::new({
+                        let cancellation = cancellation.clone();
+                        move |_| {
+                            info!("Cancel");
+                            cancellation.cancel();
+                        }
+                    }))
+                    .expect("Failed to register signal handler");
+                loop {
+                    select! {
+                        _ = cancellation.cancelled() => {
+                            break;
+                        }
+                        // The consumer of the stream stopped reading, e.g. `first 1`
+                        _ = tx.closed() => {
+                            break;
+                        }
+                        Some(message) = subscription.next() => {
+                            if tx.send(message).is_err() {
+                                break;
                             }
-                        })).expect("Failed to register signal handler");
-                        loop {
-                            select! {
-                                _ = cancellation.cancelled() => {
-                                    break;
-                                }
-                                Some(message) = subscription.next() => {
-                                    tx.send(message).expect("Failed to send message through channel");
-                                }
-                            };
                         }
-                    }
-                });
+                    };
+                }
+                if let Err(error) = client.close().await {
+                    warn!("{}", error.msg);
+                }
+            }
+        });
 
-                let handle = plugin.runtime.handle().clone();
-                let stream_iter = std::iter::repeat_with(move || handle.block_on(rx.recv()))
-                    .map_while(move |message| {
-                        message.map(|message| {
-                            if binary_output {
-                                message.payload.into_value(Span::unknown())
-                            } else {
-                                String::from_utf8_lossy(&message.payload)
-                                    .into_value(Span::unknown())
-                            }
-                        })
-                    });
+        let handle = plugin.runtime.handle().clone();
+        let stream_iter =
+            std::iter::repeat_with(move || handle.block_on(rx.recv())).map_while(move |message| {
+                message.map(|message| {
+                    if binary_output {
+                        message.payload.into_value(Span::unknown())
+                    } else {
+                        String::from_utf8_lossy(&message.payload).into_value(Span::unknown())
+                    }
+                })
+            });
 
-                Ok(PipelineData::ListStream(
-                    ListStream::new(stream_iter, call.head, Signals::empty()),
-                    None,
-                ))
-            }
-            None => Err(LabeledError::new(
-                "Not connected to NATS server. Call `nuts connect` first",
-            )),
-        }
+        Ok(PipelineData::ListStream(
+            ListStream::new(stream_iter, call.head, Signals::empty()),
+            None,
+        ))
     }
 }
@@ -0,0 +1,155 @@
+use std::{collections::BTreeMap, ops::Deref, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use async_nats::Client;
+use nu_protocol::{LabeledError, Spanned};
+
+use crate::{commands::connect::ConnectSettings, lifecycle::ActivityGuard};
+
+/// Name used for a connection when `nuts connect` is called without `--name`
+/// and no default connection is registered yet
+pub(crate) const DEFAULT_CONNECTION_NAME: &str = "default";
+
+/// Maximum time to wait for a draining connection to close
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone)]
+pub(crate) struct Connection {
+    pub(crate) client: Client,
+    pub(crate) url: String,
+    /// Settings the connection was opened with, to open more connections like it
+    pub(crate) settings: Arc<ConnectSettings>,
+}
+
+/// Registry of named connections opened with `nuts connect`
+#[derive(Debug, Default)]
+pub(crate) struct Connections {
+    connections: BTreeMap<String, Connection>,
+    default: Option<String>,
+}
+
+impl Connections {
+    /// Registers a connection, replacing any previous connection with the same name.
+    /// The first registered connection becomes the default one.
+    pub(crate) fn insert(&mut self, name: String, connection: Connection, make_default: bool) {
+        if make_default || self.default.is_none() {
+            self.default = Some(name.clone());
+        }
+        self.connections.insert(name, connection);
+    }
+
+    pub(crate) fn default_name(&self) -> Option<&str> {
+        self.default.as_deref()
+    }
+
+    /// Looks up a connection by name or the default connection if no name is given
+    pub(crate) fn get(&self, name: Option<&Spanned<String>>) -> Result<&Connection, LabeledError> {
+        match name {
+            Some(name) => self.connections.get(&name.item).ok_or_else(|| {
+                LabeledError::new(format!("No connection named `{}`", name.item)).with_label(
+                    format!("call `nuts connect --name {}` first", name.item),
+                    name.span,
+                )
+            }),
+            None => self
+                .default
+                .as_ref()
+                .and_then(|name| self.connections.get(name))
+                .ok_or_else(|| {
+                    LabeledError::new("Not connected to NATS server. Call `nuts connect` first")
+                }),
+        }
+    }
+
+    /// Unregisters a connection by name or the default connection if no name is given.
+    /// Removing the default connection leaves the registry without a default one.
+    pub(crate) fn remove(
+        &mut self,
+        name: Option<&Spanned<String>>,
+    ) -> Result<Connection, LabeledError> {
+        self.get(name)?;
+        let name = name
+            .map(|name| name.item.clone())
+            .or_else(|| self.default.clone())
+            .expect("connection existence checked above");
+        if self.default.as_ref() == Some(&name) {
+            self.default = None;
+        }
+        Ok(self
+            .connections
+            .remove(&name)
+            .expect("connection existence checked above"))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (&String, &Connection)> {
+        self.connections.iter()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+}
+
+/// Client used by a single command call. Temporary connections opened with `--server` are
+/// flushed and closed by [`ClientHandle::close`] once the command is done with them.
+/// The command counts as activity until the handle is closed or dropped, so idle connections
+/// are not closed while it runs.
+#[derive(Debug)]
+pub(crate) struct ClientHandle {
+    client: Client,
+    temporary: bool,
+    _activity: ActivityGuard,
+}
+
+impl ClientHandle {
+    pub(crate) fn registered(client: Client, activity: ActivityGuard) -> Self {
+        Self {
+            client,
+            temporary: false,
+            _activity: activity,
+        }
+    }
+
+    pub(crate) fn temporary(client: Client, activity: ActivityGuard) -> Self {
+        Self {
+            client,
+            temporary: true,
+            _activity: activity,
+        }
+    }
+
+    pub(crate) async fn close(self) -> Result<(), LabeledError> {
+        if self.temporary {
+            drain(&self.client)
+                .await
+                .map_err(|error| LabeledError::new(error.to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Deref for ClientHandle {
+    type Target = Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+/// Flushes pending publishes, drains the subscriptions and waits until the connection is closed.
+/// [`Client::drain`] only starts draining, so this waits until the connection handler is gone,
+/// which is when flushing starts to fail.
+pub(crate) async fn drain(client: &Client) -> anyhow::Result<()> {
+    client
+        .flush()
+        .await
+        .context("Failed to flush pending publishes")?;
+    client.drain().await.context("Failed to drain connection")?;
+    tokio::time::timeout(DRAIN_TIMEOUT, async {
+        while client.flush().await.is_ok() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .context("Timed out waiting for the drained connection to close")
+}
@@ -0,0 +1,137 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use nu_plugin::EngineInterface;
+use nu_protocol::{LabeledError, Spanned};
+use serde::{Deserialize, Deserializer};
+
+/// Connection settings of a context created with `nats context`
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub(crate) struct NatsContext {
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) description: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) url: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) token: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) user: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) password: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) creds: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) nkey: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) cert: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) key: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) ca: Option<String>,
+    #[serde(deserialize_with = "non_empty")]
+    pub(crate) inbox_prefix: Option<String>,
+    pub(crate) tls_first: bool,
+}
+
+/// The NATS CLI writes unset settings as empty strings
+fn non_empty<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::<String>::deserialize(deserializer)?.filter(|value| !value.is_empty()))
+}
+
+/// The NATS CLI configuration directory holding the saved contexts
+#[derive(Debug)]
+pub(crate) struct ContextStore {
+    config_dir: PathBuf,
+    home: Option<PathBuf>,
+}
+
+impl ContextStore {
+    pub(crate) fn new(engine: &EngineInterface) -> Result<Self, LabeledError> {
+        let get_env_var = |name: &str| {
+            engine
+                .get_env_var(name)?
+                .map(|value| value.coerce_into_string())
+                .transpose()
+                .map(|value| value.filter(|value| !value.is_empty()).map(PathBuf::from))
+        };
+        let home = get_env_var("HOME")?;
+        let config_dir = match get_env_var("XDG_CONFIG_HOME")? {
+            Some(config_home) => config_home,
+            None => home
+                .as_ref()
+                .map(|home| home.join(".config"))
+                .ok_or_else(|| {
+                    LabeledError::new("Failed to locate NATS contexts")
+                        .with_help("set either the HOME or XDG_CONFIG_HOME environment variable")
+                })?,
+        }
+        .join("nats");
+        Ok(Self { config_dir, home })
+    }
+
+    fn context_dir(&self) -> PathBuf {
+        self.config_dir.join("context")
+    }
+
+    /// Name of the context selected with `nats context select`
+    pub(crate) fn selected(&self) -> Option<String> {
+        fs::read_to_string(self.config_dir.join("context.txt"))
+            .ok()
+            .map(|name| name.trim().to_owned())
+            .filter(|name| !name.is_empty())
+    }
+
+    /// Names of the saved contexts in alphabetical order
+    pub(crate) fn names(&self) -> Result<Vec<String>, LabeledError> {
+        let context_dir = self.context_dir();
+        let entries = match fs::read_dir(&context_dir) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(error) => {
+                return Err(LabeledError::new(format!(
+                    "Failed to read NATS context directory {}: {error}",
+                    context_dir.display()
+                )));
+            }
+        };
+        let mut names = entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                match path.extension() {
+                    Some(extension) if extension == "json" => {
+                        Some(path.file_stem()?.to_string_lossy().into_owned())
+                    }
+                    _ => None,
+                }
+            })
+            .collect::<Vec<String>>();
+        names.sort();
+        Ok(names)
+    }
+
+    pub(crate) fn load(&self, name: &Spanned<String>) -> Result<NatsContext, LabeledError> {
+        let path = self.context_dir().join(format!("{}.json", name.item));
+        let content = fs::read_to_string(&path).map_err(|error| {
+            LabeledError::new(format!("Failed to read NATS context `{}`", name.item))
+                .with_label(format!("{}: {error}", path.display()), name.span)
+        })?;
+        serde_json::from_str(&content).map_err(|error| {
+            LabeledError::new(format!("Invalid NATS context `{}`", name.item))
+                .with_label(format!("{}: {error}", path.display()), name.span)
+        })
+    }
+
+    /// Resolves a path stored in a context, expanding a leading `~` to the home directory
+    pub(crate) fn expand_path(&self, path: &str) -> PathBuf {
+        match (path.strip_prefix("~/"), &self.home) {
+            (Some(relative), Some(home)) => home.join(relative),
+            _ => Path::new(path).to_path_buf(),
+        }
+    }
+}
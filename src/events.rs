@@ -0,0 +1,83 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use async_nats::{Event, ServerError};
+use chrono::{DateTime, FixedOffset, Local};
+use nu_protocol::{IntoValue, Record, Span, Value};
+use tokio::sync::broadcast;
+
+/// Number of events kept for `nuts events`. Older events are dropped first.
+const EVENT_BUFFER_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub(crate) struct ConnectionEvent {
+    timestamp: DateTime<FixedOffset>,
+    connection: String,
+    kind: &'static str,
+    details: Option<String>,
+}
+
+impl ConnectionEvent {
+    pub(crate) fn new(connection: String, event: Event) -> Self {
+        let (kind, details) = match event {
+            Event::Connected => ("connected", None),
+            Event::Disconnected => ("disconnected", None),
+            Event::LameDuckMode => ("lame_duck_mode", None),
+            Event::Draining => ("draining", None),
+            Event::Closed => ("closed", None),
+            Event::SlowConsumer(sid) => ("slow_consumer", Some(format!("subscription {sid}"))),
+            Event::ServerError(ServerError::SlowConsumer(sid)) => {
+                ("slow_consumer", Some(format!("subscription {sid}")))
+            }
+            Event::ServerError(error) => ("server_error", Some(error.to_string())),
+            Event::ClientError(error) => ("client_error", Some(error.to_string())),
+        };
+        Self {
+            timestamp: Local::now().fixed_offset(),
+            connection,
+            kind,
+            details,
+        }
+    }
+
+    pub(crate) fn into_value(self, span: Span) -> Value {
+        Record::from_iter([
+            ("timestamp".to_owned(), Value::date(self.timestamp, span)),
+            ("connection".to_owned(), self.connection.into_value(span)),
+            ("kind".to_owned(), self.kind.into_value(span)),
+            ("details".to_owned(), self.details.into_value(span)),
+        ])
+        .into_value(span)
+    }
+}
+
+/// Bounded history of connection events with live notification of new ones
+#[derive(Debug)]
+pub(crate) struct Events {
+    buffer: Mutex<VecDeque<ConnectionEvent>>,
+    sender: broadcast::Sender<ConnectionEvent>,
+}
+
+impl Events {
+    pub(crate) fn new() -> Self {
+        Self {
+            buffer: Mutex::new(VecDeque::with_capacity(EVENT_BUFFER_CAPACITY)),
+            sender: broadcast::channel(EVENT_BUFFER_CAPACITY).0,
+        }
+    }
+
+    pub(crate) fn push(&self, event: ConnectionEvent) {
+        let mut buffer = self.buffer.lock().unwrap();
+        if buffer.len() == EVENT_BUFFER_CAPACITY {
+            buffer.pop_front();
+        }
+        buffer.push_back(event.clone());
+        // Sending only fails if nobody follows the events
+        let _ = self.sender.send(event);
+    }
+
+    /// Returns the buffered events and a receiver for the events pushed after them
+    pub(crate) fn subscribe(&self) -> (Vec<ConnectionEvent>, broadcast::Receiver<ConnectionEvent>) {
+        let buffer = self.buffer.lock().unwrap();
+        (buffer.iter().cloned().collect(), self.sender.subscribe())
+    }
+}
@@ -0,0 +1,107 @@
+use std::sync::LazyLock;
+
+use nu_protocol::{LabeledError, Spanned, Value, engine::EngineState};
+use nuon::ToNuonConfig;
+use serde::{
+    Serialize, Serializer,
+    ser::{Error, SerializeMap, SerializeSeq},
+};
+
+/// Only needed by `to_nuon` to serialize closures, which are rejected anyway
+static ENGINE_STATE: LazyLock<EngineState> = LazyLock::new(EngineState::new);
+
+/// Format used to serialize structured values into message payloads
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum PayloadFormat {
+    Json,
+    Msgpack,
+    Nuon,
+}
+
+impl PayloadFormat {
+    pub(crate) fn from_flag(flag: Spanned<String>) -> Result<Self, LabeledError> {
+        match flag.item.as_str() {
+            "json" => Ok(Self::Json),
+            "msgpack" => Ok(Self::Msgpack),
+            "nuon" => Ok(Self::Nuon),
+            format => Err(LabeledError::new(format!("Unknown format `{format}`"))
+                .with_label("expected json, msgpack or nuon", flag.span)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::Msgpack => "msgpack",
+            Self::Nuon => "nuon",
+        }
+    }
+
+    pub(crate) fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Msgpack => "application/msgpack",
+            Self::Nuon => "application/x-nuon",
+        }
+    }
+
+    pub(crate) fn encode(self, value: &Value) -> Result<Vec<u8>, LabeledError> {
+        let encoded = match self {
+            Self::Json => {
+                serde_json::to_vec(&SerializableValue(value)).map_err(|error| error.to_string())
+            }
+            Self::Msgpack => rmp_serde::to_vec_named(&SerializableValue(value))
+                .map_err(|error| error.to_string()),
+            Self::Nuon => nuon::to_nuon(
+                &ENGINE_STATE,
+                value,
+                ToNuonConfig::default().span(Some(value.span())),
+            )
+            .map(String::into_bytes)
+            .map_err(|error| error.to_string()),
+        };
+        encoded.map_err(|error| {
+            LabeledError::new(format!("Failed to encode value as {}", self.name()))
+                .with_label(error, value.span())
+        })
+    }
+}
+
+/// Serializes values the way `to json` and `to msgpack` do. Filesizes are bytes, durations are
+/// nanoseconds and dates are RFC 3339 strings.
+struct SerializableValue<'a>(&'a Value);
+
+impl Serialize for SerializableValue<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            Value::Nothing { .. } => serializer.serialize_unit(),
+            Value::Bool { val, .. } => serializer.serialize_bool(*val),
+            Value::Int { val, .. } => serializer.serialize_i64(*val),
+            Value::Float { val, .. } => serializer.serialize_f64(*val),
+            Value::Filesize { val, .. } => serializer.serialize_i64(val.get()),
+            Value::Duration { val, .. } => serializer.serialize_i64(*val),
+            Value::String { val, .. } | Value::Glob { val, .. } => serializer.serialize_str(val),
+            Value::Date { val, .. } => serializer.serialize_str(&val.to_rfc3339()),
+            Value::Binary { val, .. } => serializer.serialize_bytes(val),
+            Value::CellPath { val, .. } => serializer.serialize_str(&val.to_string()),
+            Value::List { vals, .. } => {
+                let mut seq = serializer.serialize_seq(Some(vals.len()))?;
+                for value in vals {
+                    seq.serialize_element(&SerializableValue(value))?;
+                }
+                seq.end()
+            }
+            Value::Record { val, .. } => {
+                let mut map = serializer.serialize_map(Some(val.len()))?;
+                for (key, value) in val.iter() {
+                    map.serialize_entry(key, &SerializableValue(value))?;
+                }
+                map.end()
+            }
+            value => Err(S::Error::custom(format!(
+                "{} values cannot be encoded",
+                value.get_type()
+            ))),
+        }
+    }
+}
@@ -1,24 +1,89 @@
+#![allow(clippy::result_large_err)]
+
 mod commands;
+mod connections;
+mod context;
+mod events;
+mod format;
+mod lifecycle;
+mod message;
+mod rate_limiter;
 
 use std::sync::{Arc, RwLock};
 
-use async_nats::Client;
-use commands::{Publish, Subscribe, connect::Connect, kv};
-use nu_plugin::Plugin;
+use commands::{
+    Publish, Request, Subscribe,
+    bench::Bench,
+    connect::{self, Connect},
+    connections::ListConnections,
+    disconnect::Disconnect,
+    events::ListEvents,
+    js, kv,
+    rtt::Rtt,
+    status::Status,
+};
+use connections::{ClientHandle, Connections};
+use events::Events;
+use lifecycle::Lifecycle;
+use nu_plugin::{EngineInterface, EvaluatedCall, Plugin};
+use nu_protocol::{LabeledError, Spanned};
 use tokio::runtime::Runtime;
 
 #[derive(Debug)]
 pub struct Nuts {
     pub(crate) runtime: Runtime,
-    pub(crate) nats: Arc<RwLock<Option<Client>>>,
+    pub(crate) connections: Arc<RwLock<Connections>>,
+    pub(crate) events: Arc<Events>,
+    pub(crate) lifecycle: Arc<Lifecycle>,
 }
 
 impl Nuts {
     pub fn new(runtime: Runtime) -> Self {
+        let connections = Arc::new(RwLock::new(Connections::default()));
         Self {
+            lifecycle: Arc::new(Lifecycle::new(
+                connections.clone(),
+                runtime.handle().clone(),
+            )),
             runtime,
-            nats: Arc::new(RwLock::new(None)),
+            connections,
+            events: Arc::new(Events::new()),
+        }
+    }
+
+    /// Returns a temporary client if the call has the `--server` flag, otherwise the client
+    /// selected by the `--connection` flag or the default client if that flag is not set.
+    /// Connects lazily if the plugin configuration has settings for a missing connection.
+    pub(crate) fn client(
+        &self,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+    ) -> Result<ClientHandle, LabeledError> {
+        let name: Option<Spanned<String>> = call.get_flag("connection")?;
+        if let (Some(name), Some(_)) = (&name, call.get_flag_value("server")) {
+            return Err(LabeledError::new("Conflicting connections").with_label(
+                "`--connection` cannot be used together with `--server`",
+                name.span,
+            ));
         }
+        let client = if let Some(client) = connect::connect_from_server_flag(self, engine, call)? {
+            ClientHandle::temporary(client, self.lifecycle.activity(engine)?)
+        } else {
+            let registered = self
+                .connections
+                .read()
+                .unwrap()
+                .get(name.as_ref())
+                .map(|connection| connection.client.clone());
+            let client = match registered {
+                Ok(client) => client,
+                Err(error) => {
+                    connect::connect_from_config(self, engine, name.as_ref())?.ok_or(error)?
+                }
+            };
+            ClientHandle::registered(client, self.lifecycle.activity(engine)?)
+        };
+        Ok(client)
     }
 }
 
@@ -30,8 +95,17 @@ impl Plugin for Nuts {
     fn commands(&self) -> Vec<Box<dyn nu_plugin::PluginCommand<Plugin = Self>>> {
         vec![
             Box::new(Connect),
+            Box::new(Disconnect),
+            Box::new(ListConnections),
+            Box::new(ListEvents),
+            Box::new(commands::context::List),
+            Box::new(Status),
+            Box::new(Rtt),
+            Box::new(Bench),
             Box::new(Publish),
+            Box::new(Request),
             Box::new(Subscribe),
+            Box::new(js::Publish),
             Box::new(kv::List),
             Box::new(kv::Get),
             Box::new(kv::Put),
@@ -0,0 +1,158 @@
+use std::{
+    mem,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use nu_plugin::EngineInterface;
+use nu_protocol::LabeledError;
+use tokio::{runtime::Handle, task::JoinHandle};
+
+use crate::{
+    commands::connect::idle_timeout_from_config,
+    connections::{self, Connections},
+};
+
+/// Keeps the plugin running while connections are open or commands and streams are running by
+/// disabling the garbage collector of the engine. Closes the connections after the idle timeout of
+/// the plugin configuration elapsed since the last activity, which lets the garbage collector stop
+/// the plugin.
+#[derive(Debug)]
+pub(crate) struct Lifecycle {
+    connections: Arc<RwLock<Connections>>,
+    runtime: Handle,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    last_activity: Instant,
+    /// Number of running commands and streams
+    active: usize,
+    gc_disabled: bool,
+    idle_watcher: Option<JoinHandle<()>>,
+}
+
+impl Lifecycle {
+    pub(crate) fn new(connections: Arc<RwLock<Connections>>, runtime: Handle) -> Self {
+        Self {
+            connections,
+            runtime,
+            state: Mutex::new(State {
+                last_activity: Instant::now(),
+                active: 0,
+                gc_disabled: false,
+                idle_watcher: None,
+            }),
+        }
+    }
+
+    /// Records activity and updates the garbage collector after connections were opened or closed
+    pub(crate) fn touch(self: &Arc<Self>, engine: &EngineInterface) -> Result<(), LabeledError> {
+        let mut state = self.state.lock().unwrap();
+        state.last_activity = Instant::now();
+        self.update(&mut state, engine)
+    }
+
+    /// Keeps the plugin running and the connections open until the returned guard is dropped at
+    /// the end of a command or stream
+    pub(crate) fn activity(
+        self: &Arc<Self>,
+        engine: &EngineInterface,
+    ) -> Result<ActivityGuard, LabeledError> {
+        let mut state = self.state.lock().unwrap();
+        state.active += 1;
+        state.last_activity = Instant::now();
+        self.update(&mut state, engine)?;
+        Ok(ActivityGuard {
+            lifecycle: self.clone(),
+            engine: engine.clone(),
+        })
+    }
+
+    fn update(
+        self: &Arc<Self>,
+        state: &mut State,
+        engine: &EngineInterface,
+    ) -> Result<(), LabeledError> {
+        let live = state.active > 0 || !self.connections.read().unwrap().is_empty();
+        if live == state.gc_disabled {
+            return Ok(());
+        }
+        engine.set_gc_disabled(live)?;
+        state.gc_disabled = live;
+        if let Some(idle_watcher) = state.idle_watcher.take() {
+            idle_watcher.abort();
+        }
+        if live {
+            // Read on every start, so configuration changes apply to the next session
+            if let Some(idle_timeout) = idle_timeout_from_config(engine)? {
+                state.idle_watcher = Some(
+                    self.runtime
+                        .spawn(self.clone().close_when_idle(engine.clone(), idle_timeout)),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn close_when_idle(self: Arc<Self>, engine: EngineInterface, idle_timeout: Duration) {
+        loop {
+            let deadline = {
+                let state = self.state.lock().unwrap();
+                // Running commands and streams count as activity until they end
+                if state.active > 0 {
+                    Instant::now() + idle_timeout
+                } else {
+                    state.last_activity + idle_timeout
+                }
+            };
+            tokio::time::sleep_until(deadline.into()).await;
+            {
+                let state = self.state.lock().unwrap();
+                if state.active > 0 || state.last_activity.elapsed() < idle_timeout {
+                    continue;
+                }
+            }
+
+            info!("Closing connections after being idle for {idle_timeout:?}");
+            let connections = mem::take(&mut *self.connections.write().unwrap());
+            for (name, connection) in connections.iter() {
+                if let Err(error) = connections::drain(&connection.client).await {
+                    warn!("Failed to close idle connection `{name}`: {error:#}");
+                }
+            }
+
+            let mut state = self.state.lock().unwrap();
+            // Keep watching if a command became active while the connections were closed
+            if state.active > 0 || !self.connections.read().unwrap().is_empty() {
+                continue;
+            }
+            // Detach instead of aborting this task while it is still running
+            state.idle_watcher.take();
+            if let Err(error) = self.update(&mut state, &engine) {
+                warn!("{}", error.msg);
+            }
+            return;
+        }
+    }
+}
+
+/// Marks a running command or a live stream like `nuts sub` for [`Lifecycle`]
+#[derive(Debug)]
+pub(crate) struct ActivityGuard {
+    lifecycle: Arc<Lifecycle>,
+    engine: EngineInterface,
+}
+
+impl Drop for ActivityGuard {
+    fn drop(&mut self) {
+        let mut state = self.lifecycle.state.lock().unwrap();
+        state.active -= 1;
+        state.last_activity = Instant::now();
+        if let Err(error) = self.lifecycle.update(&mut state, &self.engine) {
+            warn!("{}", error.msg);
+        }
+    }
+}
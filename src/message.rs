@@ -0,0 +1,209 @@
+use async_nats::{
+    HeaderMap, HeaderName, HeaderValue, Message,
+    header::{IntoHeaderName, IntoHeaderValue},
+};
+use nu_protocol::{IntoValue, LabeledError, Record, Span, Value};
+
+use crate::format::PayloadFormat;
+
+/// Message built from the input of `nuts pub` and `nuts req`
+#[derive(Debug)]
+pub(crate) struct OutgoingMessage {
+    /// Overrides the subject given to the command
+    pub(crate) subject: Option<String>,
+    /// Overrides the reply subject given to the command
+    pub(crate) reply: Option<String>,
+    /// Only set for record values
+    pub(crate) headers: Option<HeaderMap>,
+    pub(crate) payload: Vec<u8>,
+}
+
+impl OutgoingMessage {
+    /// Builds a message from a string, binary or `{subject, reply, headers, payload}` record value.
+    /// Only the payload field of records is required.
+    pub(crate) fn from_value(value: Value) -> Result<Self, LabeledError> {
+        match value {
+            Value::Record {
+                val, internal_span, ..
+            } => {
+                let headers = match val.get("headers") {
+                    Some(Value::Record {
+                        val: headers,
+                        internal_span,
+                        ..
+                    }) => headers_from_record(headers, *internal_span)?,
+                    _ => HeaderMap::new(),
+                };
+                let payload = val
+                    .get("payload")
+                    .cloned()
+                    .ok_or_else(|| {
+                        LabeledError::new("missing payload")
+                            .with_label("input record must contain a payload field", internal_span)
+                    })?
+                    .coerce_into_binary()?;
+                let get_string =
+                    |key: &str| val.get(key).map(|value| value.coerce_string()).transpose();
+                Ok(Self {
+                    subject: get_string("subject")?,
+                    reply: get_string("reply")?,
+                    headers: Some(headers),
+                    payload,
+                })
+            }
+            value => Ok(Self {
+                subject: None,
+                reply: None,
+                headers: None,
+                payload: value.coerce_into_binary()?,
+            }),
+        }
+    }
+
+    /// Builds a message with the whole value serialized as payload and a matching Content-Type header
+    pub(crate) fn encode(value: &Value, format: PayloadFormat) -> Result<Self, LabeledError> {
+        Ok(Self {
+            subject: None,
+            reply: None,
+            headers: Some(HeaderMap::from_iter([(
+                "Content-Type".into_header_name(),
+                format.content_type().into_header_value(),
+            )])),
+            payload: format.encode(value)?,
+        })
+    }
+
+    /// Returns the subject of the message, falling back to the subject given to the command
+    pub(crate) fn subject_or(
+        &self,
+        subject: Option<&str>,
+        span: Span,
+    ) -> Result<String, LabeledError> {
+        self.subject
+            .as_deref()
+            .or(subject)
+            .map(ToOwned::to_owned)
+            .ok_or_else(|| {
+                LabeledError::new("Missing subject").with_label(
+                    "pass a subject argument or add a subject field to the input record",
+                    span,
+                )
+            })
+    }
+    /// Adds the headers the message does not set itself
+    pub(crate) fn add_default_headers(&mut self, defaults: &HeaderMap) {
+        if defaults.is_empty() {
+            return;
+        }
+        let headers = self.headers.get_or_insert_with(HeaderMap::new);
+        for (name, values) in defaults.iter() {
+            if headers.get(name.clone()).is_none() {
+                for value in values {
+                    headers.append(name.clone(), value.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Builds headers from a record. Lists of values set a header multiple times.
+fn headers_from_record(record: &Record, span: Span) -> Result<HeaderMap, LabeledError> {
+    let mut headers = HeaderMap::new();
+    for (name, value) in record.iter() {
+        let name = parse_header_name(name, span)?;
+        match value {
+            Value::List { vals, .. } => {
+                for value in vals {
+                    headers.append(name.clone(), parse_header_value(value)?);
+                }
+            }
+            value => headers.append(name, parse_header_value(value)?),
+        }
+    }
+    Ok(headers)
+}
+
+/// Parses `name=value` headers of a string or list flag value
+pub(crate) fn headers_from_flag(value: Value) -> Result<HeaderMap, LabeledError> {
+    let values = match value {
+        Value::List { vals, .. } => vals,
+        value => vec![value],
+    };
+    let mut headers = HeaderMap::new();
+    for value in values {
+        let span = value.span();
+        let header = value.coerce_into_string()?;
+        let (name, value) = header.split_once('=').ok_or_else(|| {
+            LabeledError::new("Invalid header")
+                .with_label("expected a header in the form of name=value", span)
+        })?;
+        headers.append(
+            parse_header_name(name, span)?,
+            parse_header_value(&Value::string(value, span))?,
+        );
+    }
+    Ok(headers)
+}
+
+/// Header names must be printable ASCII characters without colons
+fn parse_header_name(name: &str, span: Span) -> Result<HeaderName, LabeledError> {
+    name.parse::<HeaderName>()
+        .ok()
+        .filter(|_| {
+            !name.is_empty()
+                && name
+                    .chars()
+                    .all(|char| char.is_ascii_graphic() && char != ':')
+        })
+        .ok_or_else(|| {
+            LabeledError::new(format!("Invalid header name `{name}`")).with_label(
+                "header names must be printable ASCII characters without colons",
+                span,
+            )
+        })
+}
+
+/// Header values must not contain line breaks
+fn parse_header_value(value: &Value) -> Result<HeaderValue, LabeledError> {
+    let span = value.span();
+    let value = value.coerce_str()?;
+    value.parse::<HeaderValue>().map_err(|_| {
+        LabeledError::new(format!("Invalid header value `{}`", value.escape_debug()))
+            .with_label("header values must not contain line breaks", span)
+    })
+}
+
+/// Converts a received message into a record with subject, headers and payload.
+/// Header values are strings, or lists of strings if a header is set multiple times.
+pub(crate) fn message_to_value(message: Message, binary_payload: bool, span: Span) -> Value {
+    let headers = message
+        .headers
+        .iter()
+        .flat_map(|headers| headers.iter())
+        .map(|(name, values)| {
+            let value = match values.as_slice() {
+                [value] => value.to_string().into_value(span),
+                values => values
+                    .iter()
+                    .map(|value| value.to_string().into_value(span))
+                    .collect::<Vec<Value>>()
+                    .into_value(span),
+            };
+            (name.to_string(), value)
+        })
+        .collect::<Record>();
+    let payload = if binary_payload {
+        message.payload.into_value(span)
+    } else {
+        String::from_utf8_lossy(&message.payload).into_value(span)
+    };
+    Record::from_iter([
+        (
+            "subject".to_owned(),
+            message.subject.to_string().into_value(span),
+        ),
+        ("headers".to_owned(), headers.into_value(span)),
+        ("payload".to_owned(), payload),
+    ])
+    .into_value(span)
+}
@@ -0,0 +1,209 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use nu_protocol::{LabeledError, Value};
+
+use crate::commands::connect::value_to_duration;
+
+/// Number of operations allowed per period
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rate {
+    count: u32,
+    period: Duration,
+}
+
+impl Rate {
+    /// Parses an int of operations per second or a `count/duration` string like `100/10ms`
+    pub(crate) fn from_value(value: Value) -> Result<Self, LabeledError> {
+        let span = value.span();
+        let invalid = || {
+            LabeledError::new("Invalid rate").with_label(
+                "expected a positive number of messages per second or a rate like 100/10ms",
+                span,
+            )
+        };
+        let (count, period) = match value {
+            Value::Int { val, .. } => (val, Duration::from_secs(1)),
+            Value::String { val, .. } => {
+                let (count, period) = val.split_once('/').ok_or_else(invalid)?;
+                let count = count.trim().parse::<i64>().map_err(|_| invalid())?;
+                let period = period.trim();
+                // `100/sec` is the same as `100/1sec`
+                let period = if period.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+                    period.to_owned()
+                } else {
+                    format!("1{period}")
+                };
+                (count, value_to_duration(Value::string(period, span))?)
+            }
+            _ => return Err(invalid()),
+        };
+        Ok(Self {
+            count: u32::try_from(count)
+                .ok()
+                .filter(|count| *count > 0)
+                .ok_or_else(invalid)?,
+            period: Some(period)
+                .filter(|period| !period.is_zero())
+                .ok_or_else(invalid)?,
+        })
+    }
+}
+
+/// Token bucket shared between tasks. Tokens are refilled at the configured rate up to the
+/// burst size, and every operation takes one token.
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    token_interval: Duration,
+    burst: u32,
+    state: Mutex<State>,
+}
+
+#[derive(Debug)]
+struct State {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: Rate, burst: u32) -> Self {
+        let burst = burst.max(1);
+        Self {
+            token_interval: rate.period / rate.count,
+            burst,
+            state: Mutex::new(State {
+                tokens: f64::from(burst),
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a token is available and takes it
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let refilled = now.duration_since(state.refilled_at).as_secs_f64()
+                    / self.token_interval.as_secs_f64();
+                state.tokens = (state.tokens + refilled).min(f64::from(self.burst));
+                state.refilled_at = now;
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                self.token_interval.mul_f64(1.0 - state.tokens)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(value: Value) -> Result<(u32, Duration), LabeledError> {
+        Rate::from_value(value).map(|rate| (rate.count, rate.period))
+    }
+
+    fn rate_str(rate: &str) -> Result<(u32, Duration), LabeledError> {
+        self::rate(Value::test_string(rate))
+    }
+
+    #[test]
+    fn parses_messages_per_second() {
+        assert_eq!(
+            rate(Value::test_int(100)).unwrap(),
+            (100, Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn parses_rate_per_duration() {
+        assert_eq!(
+            rate_str("100/10ms").unwrap(),
+            (100, Duration::from_millis(10))
+        );
+        assert_eq!(
+            rate_str(" 5 / 2min ").unwrap(),
+            (5, Duration::from_secs(120))
+        );
+        assert_eq!(
+            rate_str("100/1.5s").unwrap(),
+            (100, Duration::from_millis(1500))
+        );
+    }
+
+    #[test]
+    fn parses_unit_without_number_as_one() {
+        assert_eq!(rate_str("100/sec").unwrap(), (100, Duration::from_secs(1)));
+        assert_eq!(rate_str("100/ms").unwrap(), (100, Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn parses_fraction_without_leading_zero() {
+        assert_eq!(
+            rate_str("100/.5s").unwrap(),
+            (100, Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rates() {
+        assert!(rate(Value::test_int(0)).is_err());
+        assert!(rate(Value::test_int(-1)).is_err());
+        assert!(rate(Value::test_bool(true)).is_err());
+        assert!(rate_str("100").is_err());
+        assert!(rate_str("0/1s").is_err());
+        assert!(rate_str("-5/1s").is_err());
+        assert!(rate_str("many/1s").is_err());
+        assert!(rate_str("100/0s").is_err());
+        assert!(rate_str("100/forever").is_err());
+    }
+
+    fn acquire_times(rate: Rate, burst: u32, times: usize) -> Duration {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
+        let rate_limiter = RateLimiter::new(rate, burst);
+        let start = Instant::now();
+        runtime.block_on(async {
+            for _ in 0..times {
+                rate_limiter.acquire().await;
+            }
+        });
+        start.elapsed()
+    }
+
+    #[test]
+    fn rate_limiter_allows_burst_immediately() {
+        let rate = Rate {
+            count: 1,
+            period: Duration::from_secs(10),
+        };
+        assert!(acquire_times(rate, 3, 3) < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn rate_limiter_waits_for_tokens() {
+        let rate = Rate {
+            count: 1,
+            period: Duration::from_millis(20),
+        };
+        // The first token is available immediately, the others take 20ms each
+        assert!(acquire_times(rate, 1, 4) >= Duration::from_millis(55));
+    }
+
+    #[test]
+    fn rate_limiter_treats_zero_burst_as_one() {
+        let rate = Rate {
+            count: 1,
+            period: Duration::from_secs(10),
+        };
+        assert_eq!(RateLimiter::new(rate, 0).burst, 1);
+    }
+}
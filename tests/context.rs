@@ -0,0 +1,105 @@
+//! Reads the NATS CLI contexts from a temporary configuration directory with the plugin commands
+
+#![allow(clippy::result_large_err)]
+
+use std::{env, fs, path::PathBuf, process};
+
+use nu_plugin_nuts::Nuts;
+use nu_plugin_test_support::PluginTest;
+use nu_protocol::{ShellError, Span, Value};
+use tokio::runtime::Runtime;
+
+/// NATS CLI configuration directory with a `dev` context, removed when dropped
+struct ConfigHome {
+    path: PathBuf,
+}
+
+impl ConfigHome {
+    fn new(test: &str, selected: Option<&str>) -> Self {
+        let path = env::temp_dir().join(format!("nu_plugin_nuts_{test}_{}", process::id()));
+        let context_dir = path.join("nats").join("context");
+        fs::create_dir_all(&context_dir).expect("Failed to create the context directory");
+        fs::write(
+            context_dir.join("dev.json"),
+            r#"{"description": "Development", "url": "nats://127.0.0.1:1", "creds": "/nonexistent/dev.creds"}"#,
+        )
+        .expect("Failed to write the context");
+        if let Some(selected) = selected {
+            fs::write(path.join("nats").join("context.txt"), selected)
+                .expect("Failed to select the context");
+        }
+        Self { path }
+    }
+}
+
+impl Drop for ConfigHome {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+fn plugin_test(config_home: &ConfigHome) -> PluginTest {
+    let runtime = Runtime::new().expect("Failed to create tokio runtime");
+    let mut plugin_test =
+        PluginTest::new("nuts", Nuts::new(runtime).into()).expect("Failed to load the plugin");
+    let current_dir = env::current_dir().expect("Failed to get the current directory");
+    plugin_test.engine_state_mut().add_env_var(
+        "PWD".to_owned(),
+        Value::test_string(current_dir.to_string_lossy()),
+    );
+    plugin_test.engine_state_mut().add_env_var(
+        "XDG_CONFIG_HOME".to_owned(),
+        Value::test_string(config_home.path.to_string_lossy()),
+    );
+    plugin_test
+}
+
+fn eval(plugin_test: &mut PluginTest, source: &str) -> Result<Value, ShellError> {
+    plugin_test.eval(source)?.into_value(Span::test_data())
+}
+
+fn error_message(result: Result<Value, ShellError>) -> String {
+    match result {
+        Ok(value) => panic!("Expected an error, got {value:?}"),
+        Err(ShellError::LabeledError(error)) => error.msg,
+        Err(error) => panic!("Expected a labeled error, got {error:?}"),
+    }
+}
+
+#[test]
+fn connect_falls_back_to_selected_context() {
+    let config_home = ConfigHome::new("selected_context", Some("dev"));
+    let message = error_message(eval(&mut plugin_test(&config_home), "nuts connect"));
+    assert_eq!(
+        message,
+        "Failed to read credentials file of NATS context `dev`"
+    );
+}
+
+#[test]
+fn connect_with_url_ignores_selected_context() {
+    let config_home = ConfigHome::new("url_over_context", Some("dev"));
+    let message = error_message(eval(
+        &mut plugin_test(&config_home),
+        "nuts connect nats://127.0.0.1:1",
+    ));
+    assert_eq!(message, "Failed to connect to NATS server");
+}
+
+#[test]
+fn context_list_marks_selected_context() {
+    let config_home = ConfigHome::new("context_list", Some("dev"));
+    let contexts = eval(&mut plugin_test(&config_home), "nuts context list").unwrap();
+    let contexts = contexts.as_list().unwrap();
+    assert_eq!(contexts.len(), 1);
+    let context = contexts[0].as_record().unwrap();
+    assert_eq!(
+        context.columns().collect::<Vec<&String>>(),
+        ["selected", "name", "description", "url"]
+    );
+    assert_eq!(context.get("selected"), Some(&Value::test_bool(true)));
+    assert_eq!(
+        context.get("name").and_then(|name| name.as_str().ok()),
+        Some("dev")
+    );
+}
@@ -0,0 +1,202 @@
+//! Connects through WebSocket URLs with the plugin commands. The tests running against a local
+//! `nats-server` with a WebSocket listener need the server binary on the `PATH`, so they are
+//! ignored by default. Run them with `cargo test -- --ignored`.
+
+#![allow(clippy::result_large_err)]
+
+use std::{
+    env, fs,
+    net::{TcpListener, TcpStream},
+    process::{Child, Command},
+    thread,
+    time::{Duration, Instant},
+};
+
+use futures::StreamExt;
+use nu_plugin_nuts::Nuts;
+use nu_plugin_test_support::PluginTest;
+use nu_protocol::{ShellError, Value};
+use tokio::runtime::Runtime;
+
+struct NatsServer {
+    process: Child,
+    port: u16,
+    websocket_port: u16,
+}
+
+impl NatsServer {
+    fn start() -> Self {
+        let port = free_port();
+        let websocket_port = free_port();
+        let config = env::temp_dir().join(format!("nu_plugin_nuts_ws_{websocket_port}.conf"));
+        fs::write(
+            &config,
+            format!("port: {port}\nwebsocket {{\n  port: {websocket_port}\n  no_tls: true\n}}\n"),
+        )
+        .expect("Failed to write nats-server config");
+        let process = Command::new("nats-server")
+            .arg("--config")
+            .arg(&config)
+            .spawn()
+            .expect("Failed to start nats-server. Is it on the PATH?");
+
+        let started = Instant::now();
+        while TcpStream::connect(("127.0.0.1", websocket_port)).is_err() {
+            assert!(
+                started.elapsed() < Duration::from_secs(10),
+                "nats-server did not start listening"
+            );
+            thread::sleep(Duration::from_millis(50));
+        }
+        Self {
+            process,
+            port,
+            websocket_port,
+        }
+    }
+}
+
+impl Drop for NatsServer {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("Failed to find a free port")
+        .port()
+}
+
+fn plugin_test() -> PluginTest {
+    let runtime = Runtime::new().expect("Failed to create tokio runtime");
+    let mut plugin_test =
+        PluginTest::new("nuts", Nuts::new(runtime).into()).expect("Failed to load the plugin");
+    // `nuts connect` resolves the TLS file paths relative to the current directory
+    let current_dir = env::current_dir().expect("Failed to get the current directory");
+    plugin_test.engine_state_mut().add_env_var(
+        "PWD".to_owned(),
+        Value::test_string(current_dir.to_string_lossy()),
+    );
+    plugin_test
+}
+
+fn eval(plugin_test: &mut PluginTest, source: &str) -> Result<Value, ShellError> {
+    plugin_test
+        .eval(source)?
+        .into_value(nu_protocol::Span::test_data())
+}
+
+fn error_message(result: Result<Value, ShellError>) -> String {
+    match result {
+        Ok(value) => panic!("Expected an error, got {value:?}"),
+        Err(ShellError::LabeledError(error)) => error.msg,
+        Err(error) => panic!("Expected a labeled error, got {error:?}"),
+    }
+}
+
+#[test]
+fn connect_rejects_required_tls_for_plain_websocket() {
+    let message = error_message(eval(
+        &mut plugin_test(),
+        "nuts connect ws://127.0.0.1:1 --tls-required",
+    ));
+    assert_eq!(
+        message,
+        "TLS is required but `ws://127.0.0.1:1` is an unencrypted WebSocket URL"
+    );
+}
+
+#[test]
+fn connect_rejects_tls_certificates_for_plain_websocket() {
+    let message = error_message(eval(
+        &mut plugin_test(),
+        "nuts connect [nats://127.0.0.1:1 ws://127.0.0.1:2] --tls-ca ca.pem",
+    ));
+    assert_eq!(
+        message,
+        "TLS certificates are given but `ws://127.0.0.1:2` is an unencrypted WebSocket URL"
+    );
+}
+
+#[test]
+fn temporary_connection_rejects_invalid_websocket_url() {
+    let message = error_message(eval(
+        &mut plugin_test(),
+        "nuts status --server 'ws://127.0.0.1:port'",
+    ));
+    assert_eq!(message, "Invalid server URL `ws://127.0.0.1:port`");
+}
+
+#[test]
+#[ignore = "requires nats-server on the PATH"]
+fn publish_over_websocket_connection() {
+    let server = NatsServer::start();
+    let runtime = Runtime::new().unwrap();
+    // Receive through the plain NATS port to check the message went through the WebSocket
+    let mut subscription = runtime.block_on(async {
+        let client = async_nats::connect(format!("127.0.0.1:{}", server.port))
+            .await
+            .expect("Failed to connect");
+        let subscription = client.subscribe("websocket.test").await.unwrap();
+        client.flush().await.unwrap();
+        subscription
+    });
+
+    let mut plugin_test = plugin_test();
+    eval(
+        &mut plugin_test,
+        &format!("nuts connect ws://127.0.0.1:{}", server.websocket_port),
+    )
+    .expect("Failed to connect through WebSocket");
+    let status = eval(&mut plugin_test, "nuts status").unwrap();
+    assert_eq!(
+        status
+            .get_data_by_key("state")
+            .map(|state| state.as_str().unwrap().to_owned()),
+        Some("connected".to_owned())
+    );
+    eval(&mut plugin_test, "'message' | nuts pub websocket.test").unwrap();
+
+    let message = runtime.block_on(async {
+        tokio::time::timeout(Duration::from_secs(5), subscription.next())
+            .await
+            .expect("Timed out waiting for the message")
+            .expect("Subscription closed")
+    });
+    assert_eq!(message.payload.as_ref(), b"message");
+}
+
+#[test]
+#[ignore = "requires nats-server on the PATH"]
+fn publish_over_temporary_websocket_connection() {
+    let server = NatsServer::start();
+    let runtime = Runtime::new().unwrap();
+    let mut subscription = runtime.block_on(async {
+        let client = async_nats::connect(format!("127.0.0.1:{}", server.port))
+            .await
+            .expect("Failed to connect");
+        let subscription = client.subscribe("websocket.test").await.unwrap();
+        client.flush().await.unwrap();
+        subscription
+    });
+
+    eval(
+        &mut plugin_test(),
+        &format!(
+            "'message' | nuts pub websocket.test --server ws://127.0.0.1:{}",
+            server.websocket_port
+        ),
+    )
+    .expect("Failed to publish through WebSocket");
+
+    let message = runtime.block_on(async {
+        tokio::time::timeout(Duration::from_secs(5), subscription.next())
+            .await
+            .expect("Timed out waiting for the message")
+            .expect("Subscription closed")
+    });
+    assert_eq!(message.payload.as_ref(), b"message");
+}